@@ -1,5 +1,6 @@
 use async_std::sync::RwLock;
 use async_std::task;
+use futures::sink;
 use futures::stream::StreamExt;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
@@ -132,29 +133,24 @@ async fn main() -> Result<(), std::io::Error> {
     app.at("/ws")
         .get(WebSocket::new(move |req: Request<Backend>, stream| {
             let backend = req.state().clone();
-            let (client, mut out_rx) = RpcClient::new();
+            let (client, out_rx) = RpcClient::new();
             let backend_session = Session::new(req.remote(), backend, client.clone());
             let session = RpcSession::new(client, backend_session);
-            let stream_rx = stream.clone();
-            task::spawn(async move {
-                while let Some(message) = out_rx.next().await {
-                    let message = serde_json::to_string(&message)?;
-                    stream.send(WsMessage::Text(message)).await?;
-                }
-                let res: Result<(), anyhow::Error> = Ok(());
-                res
-            });
             async move {
-                let sink = session.into_sink();
-                stream_rx
-                    .filter_map(|msg| async move {
-                        match msg {
-                            Ok(WsMessage::Text(input)) => Some(Ok(input)),
-                            _ => None,
-                        }
-                    })
-                    .forward(sink)
-                    .await?;
+                let incoming = stream.clone().filter_map(|msg| async move {
+                    match msg {
+                        Ok(WsMessage::Text(input)) => Some(Ok(input)),
+                        Ok(_) => None,
+                        Err(err) => Some(Err(err)),
+                    }
+                });
+                let outgoing = sink::unfold(stream, |stream, message: String| async move {
+                    stream.send_string(message).await?;
+                    Ok::<_, tide::Error>(stream)
+                });
+                if let Err(err) = yerpc::serve(session, out_rx, incoming, outgoing).await {
+                    log::warn!("Client disconnected with error: {err}");
+                }
                 Ok(())
             }
         }));
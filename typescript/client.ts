@@ -1,9 +1,16 @@
 import { Emitter } from "./util/emitter.js";
 import { Request, Response, Message, Error, Params, Id } from "./jsonrpc.js";
 
+export type ProgressCallback = (progress: unknown) => void;
+
 export interface Transport {
-  request: (method: string, params?: Params) => Promise<unknown>;
+  request: (
+    method: string,
+    params?: Params,
+    onProgress?: ProgressCallback
+  ) => Promise<unknown>;
   notification: (method: string, params?: Params) => void;
+  subscribe?: (method: string, params?: Params) => AsyncIterable<unknown>;
 }
 
 type RequestMap = Map<
@@ -11,6 +18,13 @@ type RequestMap = Map<
   { resolve: (result: unknown) => void; reject: (error: Error) => void }
 >;
 
+type SubscriptionHandler = {
+  push: (item: unknown) => void;
+  end: (error?: Error) => void;
+};
+
+type SubscriptionMap = Map<Id, SubscriptionHandler>;
+
 type ClientEvents<T> = {
   request: (request: Request) => void;
 } & T;
@@ -21,6 +35,10 @@ export abstract class BaseTransport<T = {}>
 {
   private _requests: RequestMap = new Map();
   private _requestId = 0;
+  private _subscriptions: SubscriptionMap = new Map();
+  // Handlers of subscriptions whose request is not answered yet, by request id.
+  private _pendingSubscriptions: Map<Id, SubscriptionHandler> = new Map();
+  private _progress: Map<Id, ProgressCallback> = new Map();
   _send(_message: Message): void {
     throw new Error("_send method not implemented");
   }
@@ -28,8 +46,32 @@ export abstract class BaseTransport<T = {}>
   close() {}
 
   protected _onmessage(message: Message): void {
+    if (Array.isArray(message)) {
+      for (const item of message) this._onmessage(item);
+      return;
+    }
+
     if ((message as Request).method) {
       const request = message as Request;
+      const params = request.params as {
+        id?: Id;
+        subscription?: Id;
+        item?: unknown;
+        value?: unknown;
+        error?: Error;
+      };
+      if (request.method === "$/progress") {
+        this._progress.get(params.id ?? null)?.(params.value);
+        return;
+      }
+      if (request.method === "$/subscription") {
+        this._subscriptions.get(params.subscription ?? null)?.push(params.item);
+        return;
+      }
+      if (request.method === "$/subscriptionEnd") {
+        this._subscriptions.get(params.subscription ?? null)?.end(params.error);
+        return;
+      }
       this.emit("request", request);
     }
 
@@ -38,6 +80,14 @@ export abstract class BaseTransport<T = {}>
     if (!response.id) return; // TODO: Handle error.
     const handler = this._requests.get(response.id);
     if (!handler) return; // TODO: Handle error.
+    this._requests.delete(response.id);
+    this._progress.delete(response.id);
+    // Register the subscription before later messages can carry its items.
+    const subscription = this._pendingSubscriptions.get(response.id);
+    this._pendingSubscriptions.delete(response.id);
+    if (subscription && !response.error) {
+      this._subscriptions.set(response.result as Id, subscription);
+    }
     if (response.error) handler.reject(response.error);
     else handler.resolve(response.result);
   }
@@ -52,7 +102,20 @@ export abstract class BaseTransport<T = {}>
     this._send(request);
   }
 
-  request(method: string, params?: Params): Promise<unknown> {
+  request(
+    method: string,
+    params?: Params,
+    onProgress?: ProgressCallback
+  ): Promise<unknown> {
+    return this._request(method, params, onProgress);
+  }
+
+  private _request(
+    method: string,
+    params?: Params,
+    onProgress?: ProgressCallback,
+    subscription?: SubscriptionHandler
+  ): Promise<unknown> {
     // console.log('request', { method, params }, 'this', this)
     const id: number = ++this._requestId;
     const request: Request = {
@@ -61,9 +124,43 @@ export abstract class BaseTransport<T = {}>
       id,
       params,
     };
-    this._send(request as Message);
-    return new Promise((resolve, reject) => {
+    if (onProgress) this._progress.set(id, onProgress);
+    if (subscription) this._pendingSubscriptions.set(id, subscription);
+    const response = new Promise((resolve, reject) => {
       this._requests.set(id, { resolve, reject });
     });
+    this._send(request as Message);
+    return response;
+  }
+
+  async *subscribe(method: string, params?: Params): AsyncIterable<unknown> {
+    const items: unknown[] = [];
+    let ended = false;
+    let error: Error | undefined;
+    let wake = () => {};
+    const subscription = (await this._request(method, params, undefined, {
+      push: (item) => {
+        items.push(item);
+        wake();
+      },
+      end: (err) => {
+        ended = true;
+        error = err;
+        wake();
+      },
+    })) as Id;
+    try {
+      while (items.length > 0 || !ended) {
+        if (items.length > 0) yield items.shift();
+        else await new Promise<void>((resolve) => (wake = () => resolve()));
+      }
+      if (error) throw error;
+    } finally {
+      this._subscriptions.delete(subscription);
+      // Stop the subscription on the server if the consumer breaks early.
+      if (!ended) {
+        this.request("$/unsubscribe", { subscription }).catch(() => {});
+      }
+    }
   }
 }
@@ -8,8 +8,8 @@ export type JSONValue =
   | JSONValue[]
   | { [key: string]: JSONValue };
 export type Params = JSONValue[] | Record<string, JSONValue>;
-export type U32 = number;
-export type Id = U32 | string;
+export type I64 = number;
+export type Id = I64 | string | null;
 export type Request = {
   jsonrpc?: "2.0";
   method: string;
@@ -24,4 +24,4 @@ export type Response = {
   result?: JSONValue;
   error?: Error;
 };
-export type Message = Request | Response;
+export type Message = Request | Response | Message[];
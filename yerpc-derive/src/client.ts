@@ -3,12 +3,15 @@
 import * as T from "./types.js"
 import * as RPC from "./jsonrpc.js"
 
-type RequestMethod = (method: string, params?: RPC.Params) => Promise<unknown>;
+type ProgressCallback = (progress: unknown) => void;
+type RequestMethod = (method: string, params?: RPC.Params, onProgress?: ProgressCallback) => Promise<unknown>;
 type NotificationMethod = (method: string, params?: RPC.Params) => void;
+type SubscribeMethod = (method: string, params?: RPC.Params) => AsyncIterable<unknown>;
 
 interface Transport {
   request: RequestMethod,
-  notification: NotificationMethod
+  notification: NotificationMethod,
+  subscribe?: SubscribeMethod
 }
 
 export class RawClient {
@@ -16,3 +19,4 @@ export class RawClient {
 
 #methods
 }
+#errors
\ No newline at end of file
@@ -8,15 +8,18 @@ use darling::{FromAttributes, FromMeta};
 use openrpc::generate_openrpc_generator;
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, AttributeArgs, Item};
+use syn::{parse_macro_input, AttributeArgs, DeriveInput, Item};
 
 #[cfg(feature = "openrpc")]
 mod openrpc;
 mod parse;
 mod rpc;
+mod rpc_error;
+mod rust_client;
 mod ts;
-pub(crate) use parse::{Inputs, RpcInfo};
+pub(crate) use parse::{strip_arg_attrs, Inputs, RpcInfo};
 pub(crate) use rpc::generate_rpc_impl;
+pub(crate) use rust_client::generate_rust_client;
 pub(crate) use ts::generate_typescript_generator;
 pub(crate) mod util;
 
@@ -24,20 +27,31 @@ pub(crate) mod util;
 ///
 /// ### Root Attribute Arguments:
 /// - `all_positional: bool` Positional mode means that the parameters of the RPC call are expected to be a JSON array,
-/// which will be parsed as a tuple of this function's arguments.
+///   which will be parsed as a tuple of this function's arguments.
 /// - `ts_outdir: Option<String>` Set the path where typescript definitions are written to (relative to the crate root).
-/// If not set, no typescript definitions will be written.
+///   If not set, no typescript definitions will be written.
 /// - `openrpc_outdir: Option<String>` Set the path where openrpc specification file will be written to (relative to the crate root).
-/// If not set, no openrpc definition file will be written.
+///   If not set, no openrpc definition file will be written.
+/// - `rust_client: bool` Generate a `<Type>Client` struct wrapping an `RpcClient`
+///   with one typed method per RPC method, except for subscriptions.
 ///
 /// Note that you need to specify atleast one type definition output: `ts_outdir`, `openrpc_outdir` or both.
 ///
 /// ### Method Attribute Arguments:
 /// - `name: Option<String>` Set the name of the RPC method. Defaults to the function name.
 /// - `notification: bool` Make this a notification method. Notifications are received like method calls but cannot
-/// return anything.
+///   return anything.
 /// - `positional: bool` Positional mode means that the parameters of the RPC call are expected to be a JSON array,
-/// which will be parsed as a tuple of this function's arguments.
+///   which will be parsed as a tuple of this function's arguments.
+/// - `subscription: bool` Make this a subscription method. Subscription methods return a stream whose items are
+///   sent to the client as notifications.
+/// - `typed_error: bool` Export the error type `E` of the returned `Result<T, E>` in the type definitions.
+///   `E` has to implement `RpcError`, usually with `#[derive(RpcError)]`.
+///
+/// ### Extractor Arguments:
+/// Arguments marked with `#[rpc(context)]`, such as `RequestId`, `MethodName`, `RpcClient`, `Meta<T>` and
+/// `Progress<T>`, are filled from the request context instead of the request params and are left out of the
+/// generated type definitions.
 #[proc_macro_attribute]
 pub fn rpc(attr: TokenStream, tokens: TokenStream) -> TokenStream {
     let item = parse_macro_input!(tokens as Item);
@@ -63,6 +77,11 @@ pub fn rpc(attr: TokenStream, tokens: TokenStream) -> TokenStream {
                 quote!()
             };
             let rpc_impl = generate_rpc_impl(&info);
+            let client_impl = if attr_args.rust_client {
+                generate_rust_client(&info)
+            } else {
+                quote!()
+            };
 
             #[cfg(feature = "openrpc")]
             let openrpc_impl = if let Some(outdir) = attr_args.openrpc_outdir.as_ref() {
@@ -74,9 +93,11 @@ pub fn rpc(attr: TokenStream, tokens: TokenStream) -> TokenStream {
             #[cfg(not(feature = "openrpc"))]
             let openrpc_impl = quote!();
 
+            let input = strip_arg_attrs(input);
             quote! {
-                #item
+                #input
                 #rpc_impl
+                #client_impl
                 #ts_impl
                 #openrpc_impl
             }
@@ -91,6 +112,21 @@ pub fn rpc(attr: TokenStream, tokens: TokenStream) -> TokenStream {
     .into()
 }
 
+/// Derives `RpcError` and the conversion into `yerpc::Error` for an error enum.
+///
+/// Each variant needs a `#[rpc_error(code = ..., message = "...")]` attribute.
+/// The message defaults to the variant name.
+/// Fields of the variant are sent as the `data` of the error object:
+/// a single unnamed field as is, multiple unnamed fields as an array
+/// and named fields as an object.
+#[proc_macro_derive(RpcError, attributes(rpc_error))]
+pub fn derive_rpc_error(tokens: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(tokens as DeriveInput);
+    rpc_error::generate_rpc_error(&input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
 #[derive(FromMeta, Debug, Default)]
 #[darling(default)]
 pub(crate) struct RootAttrArgs {
@@ -103,6 +139,8 @@ pub(crate) struct RootAttrArgs {
     /// Set the path where openrpc definitions will be written to (relative to the crate root).
     /// If not set, no openrpc definitions will be written.
     openrpc_outdir: Option<String>,
+    /// Generate a typed Rust client wrapping an `RpcClient`.
+    rust_client: bool,
 }
 
 #[derive(FromAttributes, Debug, Default)]
@@ -112,9 +150,20 @@ pub(crate) struct MethodAttrArgs {
     name: Option<String>,
     /// Make this a notification method. Notifications are received like method calls but cannot
     /// return anything.
-    #[darling(default)]
     notification: bool,
     /// Positional mode means that the parameters of the RPC call are expected to be a JSON array,
     /// which will be parsed as a tuple of this function's arguments.
     positional: bool,
+    /// Make this a subscription method. Subscription methods return a stream whose items are
+    /// sent to the client as notifications.
+    subscription: bool,
+    /// Export the error type of the returned `Result`, which implements `RpcError`.
+    typed_error: bool,
+}
+
+#[derive(FromAttributes, Debug, Default)]
+#[darling(default, attributes(rpc))]
+pub(crate) struct ArgAttrArgs {
+    /// Fill this argument from the request context instead of the request params.
+    context: bool,
 }
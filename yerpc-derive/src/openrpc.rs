@@ -1,6 +1,6 @@
 use crate::{
     parse::{Input, RemoteProcedure},
-    util::extract_result_ty,
+    util::{extract_result_ty, extract_stream_item_ty, is_progress_ty},
     Inputs, RpcInfo,
 };
 use convert_case::{Case, Casing};
@@ -65,11 +65,23 @@ fn generate_method(method: &RemoteProcedure, definitions: Ident) -> TokenStream
     } else {
         quote!(None)
     };
-    let output_ty = method
-        .output
-        .map(extract_result_ty)
-        .map(|ty| quote!(#ty))
-        .unwrap_or(quote!(()));
+    let is_subscription = method.is_subscription;
+    let has_progress = method.context.iter().any(|input| is_progress_ty(input.ty))
+        && !method.is_notification
+        && !is_subscription;
+    let errors = match method.error {
+        Some(ty) => quote!(::yerpc::openrpc::errors::<#ty>()),
+        None => quote!(Vec::new()),
+    };
+    let output_ty = match method.output {
+        Some(ty) if is_subscription => {
+            extract_stream_item_ty(ty).map_or_else(|| quote!(::serde_json::Value), |ty| quote!(#ty))
+        }
+        output => output
+            .map(extract_result_ty)
+            .map(|ty| quote!(#ty))
+            .unwrap_or(quote!(())),
+    };
     let output_name = format!("{}Result", name).to_case(Case::UpperCamel);
     let result = quote! {
         ::yerpc::openrpc::Param {
@@ -90,7 +102,10 @@ fn generate_method(method: &RemoteProcedure, definitions: Ident) -> TokenStream
             description: #docs,
             param_structure: #param_structure,
             params: #params,
-            result: #result
+            result: #result,
+            subscription: #is_subscription,
+            progress: #has_progress,
+            errors: #errors
         }
     }
 }
@@ -1,8 +1,9 @@
-use super::MethodAttrArgs;
+use super::{ArgAttrArgs, MethodAttrArgs};
 use darling::FromAttributes;
 // use proc_macro2::Ident;
 use syn::{FnArg, Generics, Ident, ImplItem, ImplItemMethod, ItemImpl, Pat, ReturnType, Type};
 
+use crate::util::extract_rpc_error_ty;
 use crate::RootAttrArgs;
 
 /// Result of parsing the `impl` of an RPC server.
@@ -60,6 +61,15 @@ pub(crate) struct RemoteProcedure<'s> {
     pub output: Option<&'s Type>,
     pub is_notification: bool,
 
+    /// Whether the method returns a stream of subscription items.
+    pub is_subscription: bool,
+
+    /// Typed error enum returned by the method, if marked with `typed_error`.
+    pub error: Option<&'s Type>,
+
+    /// Extractor arguments filled from the request context.
+    pub context: Vec<ContextInput<'s>>,
+
     /// Documentation extracted from the documentation comment.
     pub docs: Option<String>,
 }
@@ -71,6 +81,18 @@ pub(crate) enum Inputs<'s> {
     Structured(Option<Input<'s>>),
 }
 
+/// Description of an extractor argument.
+///
+/// It is filled from the request context instead of being read from the request params.
+#[derive(Debug)]
+pub(crate) struct ContextInput<'s> {
+    /// Position of the argument among the typed function arguments.
+    pub position: usize,
+
+    /// Type of the extractor.
+    pub ty: &'s Type,
+}
+
 /// Description of a single method parameter.
 #[derive(Debug)]
 pub(crate) struct Input<'s> {
@@ -90,6 +112,34 @@ impl<'s> Input<'s> {
     }
 }
 
+/// Returns true if the argument is marked with `#[rpc(context)]`.
+fn is_context_arg(arg: &FnArg) -> bool {
+    match arg {
+        FnArg::Typed(arg) => {
+            ArgAttrArgs::from_attributes(&arg.attrs)
+                .unwrap_or_default()
+                .context
+        }
+        FnArg::Receiver(_) => false,
+    }
+}
+
+/// Removes the `#[rpc(...)]` attributes of method arguments,
+/// which are only read by the macro.
+pub(crate) fn strip_arg_attrs(input: &ItemImpl) -> ItemImpl {
+    let mut input = input.clone();
+    for item in &mut input.items {
+        if let ImplItem::Method(method) = item {
+            for arg in &mut method.sig.inputs {
+                if let FnArg::Typed(arg) = arg {
+                    arg.attrs.retain(|attr| !attr.path.is_ident("rpc"));
+                }
+            }
+        }
+    }
+    input
+}
+
 fn parse_doc_comment(attrs: &[syn::Attribute]) -> Option<String> {
     let mut parts = vec![];
     for attr in attrs {
@@ -116,21 +166,43 @@ impl<'s> RemoteProcedure<'s> {
             ReturnType::Type(_, ref ty) => Some(ty.as_ref()),
         };
         let positional = root_attr_args.all_positional || args.positional;
-        let mut inputs_iter = method.sig.inputs.iter();
+        let mut context = vec![];
+        let mut inputs = vec![];
+        for (position, (input, is_context)) in method
+            .sig
+            .inputs
+            .iter()
+            .filter_map(|arg| Some((Input::from_arg(arg)?, is_context_arg(arg))))
+            .enumerate()
+        {
+            if is_context {
+                context.push(ContextInput {
+                    position,
+                    ty: input.ty,
+                });
+            } else {
+                inputs.push(input);
+            }
+        }
         let input = if positional {
-            let inputs = inputs_iter.filter_map(Input::from_arg);
-            Inputs::Positional(inputs.collect())
+            Inputs::Positional(inputs)
         } else {
-            let input = inputs_iter.find_map(Input::from_arg);
-            Inputs::Structured(input)
+            Inputs::Structured(inputs.into_iter().next())
         };
         let docs = parse_doc_comment(&method.attrs);
+        let typed_error = args.typed_error;
+        let error = output
+            .filter(|_| typed_error)
+            .and_then(extract_rpc_error_ty);
         Self {
             ident: &method.sig.ident,
             name,
             input,
             output,
             is_notification: args.notification,
+            is_subscription: args.subscription,
+            error,
+            context,
             docs,
         }
     }
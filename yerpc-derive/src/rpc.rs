@@ -1,59 +1,104 @@
 use crate::{util::is_result_ty, Inputs, RpcInfo};
+use convert_case::{Case, Casing};
 use proc_macro2::TokenStream;
 use quote::quote;
 
 #[cfg(feature = "openrpc")]
 use crate::openrpc::generate_doc;
 
+/// Name of a positional parameter as reported in errors,
+/// the same as in the generated TypeScript client.
+fn param_name(ident: Option<&syn::Ident>, i: usize) -> String {
+    ident
+        .map_or_else(|| format!("arg{}", i + 1), ToString::to_string)
+        .to_case(Case::Camel)
+}
+
 pub(crate) fn generate_rpc_impl(info: &RpcInfo) -> TokenStream {
     let mut request_arms = vec![];
     let mut notification_arms = vec![];
+    let mut subscription_arms = vec![];
+    let mut subscription_names = vec![];
+    let crat = quote! { ::yerpc };
 
     for method in &info.methods[..] {
         let name = &method.name;
         let ident = &method.ident;
 
-        let call = match &method.input {
+        let (decode, mut args) = match &method.input {
             // Call with an array of multiple arguments.
             Inputs::Positional(inputs) => {
                 let n_inputs = inputs.len();
-                let inputs =
-                    (0..n_inputs).map(|_| quote!(serde_json::from_value(params.next().unwrap())?));
-                quote!(
-                    let params: Vec<serde_json::Value> =
-                    if params.is_null() {
-                        Vec::new()
-                    } else {
-                        ::serde_json::from_value(params)?
+                let args = inputs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, input)| {
+                        let name = param_name(input.ident, i);
+                        quote!(#crat::deserialize_raw_param(params.next(), #name, Some(#i))?)
+                    })
+                    .collect();
+                // Arguments are split without decoding them.
+                let decode = quote!(
+                    let params: Vec<&::serde_json::value::RawValue> = match &params {
+                        None => Vec::new(),
+                        Some(params) => ::serde_json::from_str(params.get())
+                            .map_err(|_| #crat::Error::invalid_args_len(#n_inputs))?,
                     };
                     if params.len() != #n_inputs {
                         return Err(::yerpc::Error::invalid_args_len(#n_inputs));
                     }
                     let mut params = params.into_iter();
-                    let res = self.#ident(#(#inputs),*).await;
-                )
+                );
+                (decode, args)
             }
             // Call with a single argument.
-            Inputs::Structured(_input) => {
-                quote!(
-                    let params = ::serde_json::from_value(params)?;
-                    let res = self.#ident(params).await;
-                )
+            Inputs::Structured(Some(input)) => {
+                let name = input
+                    .ident
+                    .map_or_else(|| "params".to_string(), ToString::to_string)
+                    .to_case(Case::Camel);
+                let decode = quote!(
+                    let params = #crat::deserialize_raw_param(params.as_deref(), #name, None)?;
+                );
+                (decode, vec![quote!(params)])
             }
+            Inputs::Structured(None) => (quote!(), vec![]),
         };
 
+        // Extractor arguments are filled from the request context.
+        for input in &method.context {
+            let ty = input.ty;
+            let arg = quote!(<#ty as #crat::FromContext>::from_context(&context)?);
+            args.insert(input.position, arg);
+        }
+        let call = quote!(
+            #decode
+            let res = self.#ident(#(#args),*).await;
+        );
+
         let unwrap_output = match &method.output {
             Some(output) if is_result_ty(output) => quote!(let res = res?;),
             _ => quote!(),
         };
 
+        if method.is_subscription {
+            subscription_names.push(name);
+            subscription_arms.push(quote! {
+                #name => {
+                    #call
+                    #unwrap_output
+                    Ok(#crat::subscription_stream(res))
+                },
+            });
+            continue;
+        }
+
         match method.is_notification {
             false => request_arms.push(quote! {
                 #name => {
                     #call
                     #unwrap_output
-                    let res = ::serde_json::to_value(&res)?;
-                    Ok(res)
+                    #crat::to_raw_result(&res)
                 },
             }),
             true => notification_arms.push(quote! {
@@ -68,7 +113,6 @@ pub(crate) fn generate_rpc_impl(info: &RpcInfo) -> TokenStream {
     }
 
     let struc = &info.self_ty;
-    let crat = quote! { ::yerpc };
 
     #[cfg(feature = "openrpc")]
     let openrpc_doc = generate_doc(info);
@@ -84,6 +128,28 @@ pub(crate) fn generate_rpc_impl(info: &RpcInfo) -> TokenStream {
             Ok(json.to_string())
         }
     };
+    // Servers without subscriptions keep the default implementations.
+    let subscription_methods = if subscription_names.is_empty() {
+        quote! {}
+    } else {
+        quote! {
+            fn is_subscription(&self, method: &str) -> bool {
+                matches!(method, #(#subscription_names)|*)
+            }
+            #[allow(unused_variables)]
+            async fn handle_subscription(
+                &self,
+                method: String,
+                params: #crat::RawParams,
+                context: #crat::RequestContext,
+            ) -> Result<#crat::SubscriptionStream, #crat::Error> {
+                match method.as_str() {
+                    #(#subscription_arms)*
+                    _ => Err(#crat::Error::method_not_found())
+                }
+            }
+        }
+    };
     let (impl_generics, _ty_generics, where_clause) = &info.generics.split_for_impl();
 
     quote! {
@@ -97,6 +163,26 @@ pub(crate) fn generate_rpc_impl(info: &RpcInfo) -> TokenStream {
                 method: String,
                 params: ::serde_json::Value,
             ) -> Result<::serde_json::Value, #crat::Error> {
+                let context = #crat::RequestContext::new(method.as_str());
+                self.handle_request_with_context(method, params, context).await
+            }
+            async fn handle_request_with_context(
+                &self,
+                method: String,
+                params: ::serde_json::Value,
+                context: #crat::RequestContext,
+            ) -> Result<::serde_json::Value, #crat::Error> {
+                let params = #crat::value_to_raw_params(params)?;
+                let res = self.handle_request_raw(method, params, context).await?;
+                Ok(::serde_json::from_str(res.get())?)
+            }
+            #[allow(unused_variables)]
+            async fn handle_request_raw(
+                &self,
+                method: String,
+                params: #crat::RawParams,
+                context: #crat::RequestContext,
+            ) -> Result<#crat::RawResult, #crat::Error> {
                 match method.as_str() {
                     #(#request_arms)*
                     _ => Err(#crat::Error::method_not_found())
@@ -106,12 +192,32 @@ pub(crate) fn generate_rpc_impl(info: &RpcInfo) -> TokenStream {
                 &self,
                 method: String,
                 params: ::serde_json::Value,
+            ) -> Result<(), #crat::Error> {
+                let context = #crat::RequestContext::new(method.as_str());
+                self.handle_notification_with_context(method, params, context).await
+            }
+            async fn handle_notification_with_context(
+                &self,
+                method: String,
+                params: ::serde_json::Value,
+                context: #crat::RequestContext,
+            ) -> Result<(), #crat::Error> {
+                let params = #crat::value_to_raw_params(params)?;
+                self.handle_notification_raw(method, params, context).await
+            }
+            #[allow(unused_variables)]
+            async fn handle_notification_raw(
+                &self,
+                method: String,
+                params: #crat::RawParams,
+                context: #crat::RequestContext,
             ) -> Result<(), #crat::Error> {
                 match method.as_str() {
                     #(#notification_arms)*
                     _ => Err(#crat::Error::method_not_found())
                 }
             }
+            #subscription_methods
         }
     }
 }
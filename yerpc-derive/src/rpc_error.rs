@@ -0,0 +1,312 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream};
+use syn::visit_mut::{self, VisitMut};
+use syn::{
+    parse_quote, Data, DeriveInput, Expr, ExprLit, ExprUnary, Fields, Generics, Ident, Lit, LitStr,
+    Token, Type, TypePath, UnOp,
+};
+
+/// Arguments of the `#[rpc_error]` attribute of a variant.
+struct VariantArgs {
+    code: Expr,
+    message: Option<LitStr>,
+}
+
+impl Parse for VariantArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut code = None;
+        let mut message = None;
+        while !input.is_empty() {
+            let name: Ident = input.parse()?;
+            input.parse::<Token![=]>()?;
+            if name == "code" {
+                code = Some(input.parse()?);
+            } else if name == "message" {
+                message = Some(input.parse()?);
+            } else {
+                return Err(syn::Error::new_spanned(name, "Unknown argument"));
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        let code = code.ok_or_else(|| input.error("Missing error code"))?;
+        Ok(Self { code, message })
+    }
+}
+
+/// Returns the TypeScript literal type of an error code,
+/// or `number` if the code is not an integer literal.
+fn code_literal(code: &Expr) -> String {
+    match code {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(int), ..
+        }) => int.base10_digits().to_string(),
+        Expr::Unary(ExprUnary {
+            op: UnOp::Neg(_),
+            expr,
+            ..
+        }) => match code_literal(expr).as_str() {
+            "number" => "number".to_string(),
+            digits => format!("-{digits}"),
+        },
+        _ => "number".to_string(),
+    }
+}
+
+/// Returns the name of the placeholder type standing for a type parameter
+/// in the definition of a generic error enum.
+fn placeholder(param: &Ident) -> Ident {
+    format_ident!("__TypeParam_{}", param)
+}
+
+/// Returns the type expression of a field type,
+/// referring to type parameters of the enum by their placeholders.
+fn type_ref(ty: &Type, generics: &Generics) -> TokenStream {
+    struct ReplaceParams<'a>(&'a Generics);
+
+    impl VisitMut for ReplaceParams<'_> {
+        fn visit_type_path_mut(&mut self, type_path: &mut TypePath) {
+            let param = self
+                .0
+                .type_params()
+                .find(|param| type_path.path.is_ident(&param.ident));
+            if let Some(param) = param {
+                type_path.path = placeholder(&param.ident).into();
+            }
+            visit_mut::visit_type_path_mut(self, type_path);
+        }
+    }
+
+    let mut ty = ty.clone();
+    ReplaceParams(generics).visit_type_mut(&mut ty);
+    quote!(::yerpc::typescript::type_def::type_expr::TypeExpr::Ref(&<#ty as ::yerpc::TypeDef>::INFO))
+}
+
+/// Returns `generics` with `bounds` added to each type parameter.
+fn with_bounds(generics: &Generics, bounds: TokenStream) -> Generics {
+    let mut generics = generics.clone();
+    let params: Vec<_> = generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .collect();
+    let where_clause = generics.make_where_clause();
+    for param in params {
+        where_clause.predicates.push(parse_quote!(#param: #bounds));
+    }
+    generics
+}
+
+pub(crate) fn generate_rpc_error(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let crat = quote! { ::yerpc };
+    let ts = quote! { #crat::typescript::type_def::type_expr };
+    let ty = &input.ident;
+    let Data::Enum(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            input,
+            "RpcError can only be derived for enums",
+        ));
+    };
+
+    let mut arms = vec![];
+    let mut variants = vec![];
+    let mut members = vec![];
+    for variant in &data.variants {
+        let attr = variant
+            .attrs
+            .iter()
+            .find(|attr| attr.path.is_ident("rpc_error"))
+            .ok_or_else(|| {
+                syn::Error::new_spanned(variant, "Missing #[rpc_error(code = ...)] attribute")
+            })?;
+        let VariantArgs { code, message } = attr.parse_args()?;
+        let ident = &variant.ident;
+        let name = ident.to_string();
+        let message = message.map_or_else(|| name.clone(), |message| message.value());
+
+        let (pattern, data, data_ty) = match &variant.fields {
+            Fields::Unit => (quote!(), None, None),
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                let field_ty = &fields.unnamed[0].ty;
+                (
+                    quote!((value)),
+                    Some(quote!(::serde_json::to_value(value))),
+                    Some(type_ref(field_ty, &input.generics)),
+                )
+            }
+            Fields::Unnamed(fields) => {
+                let bindings: Vec<_> = (0..fields.unnamed.len())
+                    .map(|i| format_ident!("field{}", i))
+                    .collect();
+                let tys = fields
+                    .unnamed
+                    .iter()
+                    .map(|field| type_ref(&field.ty, &input.generics));
+                (
+                    quote!((#(#bindings),*)),
+                    Some(quote!(::serde_json::to_value((#(#bindings,)*)))),
+                    Some(quote!(#ts::TypeExpr::Tuple(#ts::TypeTuple {
+                        docs: None,
+                        elements: &[#(#tys),*],
+                    }))),
+                )
+            }
+            Fields::Named(fields) => {
+                let bindings: Vec<_> = fields
+                    .named
+                    .iter()
+                    .map(|field| field.ident.as_ref().unwrap())
+                    .collect();
+                let names = bindings.iter().map(ToString::to_string);
+                let entries = names.clone();
+                let tys = fields
+                    .named
+                    .iter()
+                    .map(|field| type_ref(&field.ty, &input.generics));
+                (
+                    quote!({ #(#bindings),* }),
+                    Some(quote! {
+                        ::std::iter::IntoIterator::into_iter([
+                            #((#entries, ::serde_json::to_value(#bindings))),*
+                        ])
+                        .map(|(name, value)| value.map(|value| (name.to_string(), value)))
+                        .collect::<::serde_json::Result<::serde_json::Map<_, _>>>()
+                        .map(::serde_json::Value::Object)
+                    }),
+                    Some(quote!(#ts::TypeExpr::Object(#ts::TypeObject {
+                        docs: None,
+                        index_signature: None,
+                        fields: &[#(#ts::ObjectField {
+                            docs: None,
+                            name: #ts::TypeString { docs: None, value: #names },
+                            optional: false,
+                            r#type: #tys,
+                        }),*],
+                    }))),
+                )
+            }
+        };
+
+        // Data that fails to serialize turns the error into an internal one
+        // instead of silently dropping the data.
+        let error = match data {
+            None => quote!(#crat::Error::new(#code, #message.to_string())),
+            Some(data) => quote! {
+                match #data {
+                    Ok(data) => #crat::Error::with_data(#code, #message.to_string(), Some(data)),
+                    Err(err) => #crat::Error::internal(format!(
+                        "Failed to serialize data of error {}: {}",
+                        #code, err
+                    )),
+                }
+            },
+        };
+        arms.push(quote! {
+            #ty::#ident #pattern => #error,
+        });
+        variants.push(quote! {
+            #crat::ErrorVariant {
+                name: #name,
+                code: #code,
+                message: #message,
+            }
+        });
+        let code_ty = code_literal(&code);
+        let data_field = data_ty.map(|data_ty| {
+            quote!(#ts::ObjectField {
+                docs: None,
+                name: #ts::TypeString { docs: None, value: "data" },
+                optional: false,
+                r#type: #data_ty,
+            },)
+        });
+        members.push(quote! {
+            #ts::TypeExpr::Object(#ts::TypeObject {
+                docs: None,
+                index_signature: None,
+                fields: &[
+                    #ts::ObjectField {
+                        docs: None,
+                        name: #ts::TypeString { docs: None, value: "code" },
+                        optional: false,
+                        r#type: #ts::TypeExpr::ident(#ts::Ident(#code_ty)),
+                    },
+                    #ts::ObjectField {
+                        docs: None,
+                        name: #ts::TypeString { docs: None, value: "message" },
+                        optional: false,
+                        r#type: #ts::TypeExpr::ident(#ts::Ident("string")),
+                    },
+                    #data_field
+                ],
+            })
+        });
+    }
+
+    let name = ty.to_string();
+    let params: Vec<_> = input
+        .generics
+        .type_params()
+        .map(|param| &param.ident)
+        .collect();
+    let param_names = params.iter().map(ToString::to_string);
+    let placeholders = params.iter().map(|param| {
+        let placeholder = placeholder(param);
+        let name = param.to_string();
+        quote! {
+            #[allow(non_camel_case_types)]
+            struct #placeholder;
+
+            impl #crat::TypeDef for #placeholder {
+                const INFO: #ts::TypeInfo = #ts::TypeInfo::Native(#ts::NativeTypeInfo {
+                    r#ref: #ts::TypeExpr::ident(#ts::Ident(#name)),
+                });
+            }
+        }
+    });
+    let from_generics = with_bounds(&input.generics, quote!(::serde::Serialize));
+    let (impl_generics, ty_generics, where_clause) = from_generics.split_for_impl();
+    let rpc_error_generics =
+        with_bounds(&input.generics, quote!(::serde::Serialize + #crat::TypeDef));
+    let (rpc_error_impl_generics, _, rpc_error_where_clause) = rpc_error_generics.split_for_impl();
+    let type_def_generics = with_bounds(&input.generics, quote!(#crat::TypeDef));
+    let (type_def_impl_generics, _, type_def_where_clause) = type_def_generics.split_for_impl();
+    Ok(quote! {
+        #[automatically_derived]
+        impl #impl_generics ::std::convert::From<#ty #ty_generics> for #crat::Error #where_clause {
+            fn from(error: #ty #ty_generics) -> Self {
+                match error {
+                    #(#arms)*
+                }
+            }
+        }
+
+        #[automatically_derived]
+        impl #rpc_error_impl_generics #crat::RpcError for #ty #ty_generics #rpc_error_where_clause {
+            const NAME: &'static str = #name;
+            const VARIANTS: &'static [#crat::ErrorVariant] = &[#(#variants),*];
+        }
+
+        #[automatically_derived]
+        impl #type_def_impl_generics #crat::TypeDef for #ty #ty_generics #type_def_where_clause {
+            const INFO: #ts::TypeInfo = {
+                #(#placeholders)*
+
+                #ts::TypeInfo::Defined(#ts::DefinedTypeInfo {
+                    def: #ts::TypeDefinition {
+                        docs: None,
+                        path: &[],
+                        name: #ts::Ident(#name),
+                        generic_vars: &[#(#ts::Ident(#param_names)),*],
+                        def: #ts::TypeExpr::Union(#ts::TypeUnion {
+                            docs: None,
+                            members: &[#(#members),*],
+                        }),
+                    },
+                    generic_args: &[#(#ts::TypeExpr::Ref(&<#params as #crat::TypeDef>::INFO)),*],
+                })
+            };
+        }
+    })
+}
@@ -0,0 +1,113 @@
+use crate::{util::extract_result_ty, Inputs, RpcInfo};
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::Type;
+
+/// Generates a `<Type>Client` struct with one typed method per RPC method.
+///
+/// Subscriptions are skipped as the client cannot receive their items.
+pub(crate) fn generate_rust_client(info: &RpcInfo) -> TokenStream {
+    let crat = quote! { ::yerpc };
+    let server_name = match info.self_ty {
+        Type::Path(path) => path.path.segments.last().map(|segment| &segment.ident),
+        _ => None,
+    };
+    let Some(server_name) = server_name else {
+        return syn::Error::new_spanned(info.self_ty, "Cannot derive the client name of this type")
+            .to_compile_error();
+    };
+    let client_name = format_ident!("{}Client", server_name);
+
+    let mut methods = vec![];
+    for method in info.methods.iter().filter(|method| !method.is_subscription) {
+        let name = &method.name;
+        let ident = &method.ident;
+
+        let (args, params) = match &method.input {
+            Inputs::Positional(inputs) => {
+                let args: Vec<_> = inputs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, input)| {
+                        let arg = input
+                            .ident
+                            .cloned()
+                            .unwrap_or_else(|| format_ident!("arg{}", i + 1));
+                        (arg, input.ty)
+                    })
+                    .collect();
+                // Tuples are serialized as arrays.
+                let params = match args.is_empty() {
+                    true => quote!(None::<()>),
+                    false => {
+                        let names = args.iter().map(|(arg, _)| arg);
+                        quote!(Some((#(#names,)*)))
+                    }
+                };
+                (args, params)
+            }
+            Inputs::Structured(Some(input)) => {
+                let arg = input
+                    .ident
+                    .cloned()
+                    .unwrap_or_else(|| format_ident!("params"));
+                let params = quote!(Some(#arg));
+                (vec![(arg, input.ty)], params)
+            }
+            Inputs::Structured(None) => (vec![], quote!(None::<()>)),
+        };
+        let args = args.iter().map(|(arg, ty)| quote!(#arg: #ty));
+        let docs = method.docs.as_ref().map(|docs| quote!(#[doc = #docs]));
+
+        let body = if method.is_notification {
+            quote! {
+                pub async fn #ident(&self, #(#args),*) -> #crat::Result<()> {
+                    self.client.send_notification(#name, #params).await
+                }
+            }
+        } else {
+            let output = method
+                .output
+                .map(extract_result_ty)
+                .map_or_else(|| quote!(()), |ty| quote!(#ty));
+            quote! {
+                pub async fn #ident(&self, #(#args),*) -> #crat::Result<#output> {
+                    let res = self.client.send_request(#name, #params).await?;
+                    ::serde_json::from_value(res).map_err(|err| {
+                        #crat::Error::new(
+                            #crat::Error::BAD_RESPONSE,
+                            format!("Error while processing a response: {}", err),
+                        )
+                    })
+                }
+            }
+        };
+        methods.push(quote! {
+            #docs
+            #body
+        });
+    }
+
+    let doc = format!("Typed client for the [`{server_name}`] JSON-RPC API.");
+    quote! {
+        #[doc = #doc]
+        #[derive(Clone)]
+        pub struct #client_name {
+            client: #crat::RpcClient,
+        }
+
+        impl #client_name {
+            /// Creates a typed client sending requests through `client`.
+            pub fn new(client: #crat::RpcClient) -> Self {
+                Self { client }
+            }
+
+            /// Returns the underlying untyped client.
+            pub fn client(&self) -> &#crat::RpcClient {
+                &self.client
+            }
+
+            #(#methods)*
+        }
+    }
+}
@@ -1,10 +1,14 @@
-use crate::{util::extract_result_ty, Inputs, RpcInfo};
+use crate::{
+    util::{extract_progress_ty, extract_result_ty, extract_stream_item_ty, is_progress_ty},
+    Inputs, RpcInfo,
+};
 use convert_case::{Case, Casing};
 use proc_macro2::TokenStream;
 use quote::quote;
 pub(crate) fn generate_typescript_generator(info: &RpcInfo, outdir_path: &String) -> TokenStream {
     let mut gen_types = vec![];
     let mut gen_methods = vec![];
+    let mut gen_errors = vec![];
     for method in &info.methods {
         let (is_positional, gen_args) = match &method.input {
             Inputs::Positional(ref inputs) => {
@@ -34,6 +38,12 @@ pub(crate) fn generate_typescript_generator(info: &RpcInfo, outdir_path: &String
             Inputs::Structured(None) => (false, vec![]),
         };
         let gen_output = match (method.output, method.is_notification) {
+            (Some(ty), _) if method.is_subscription => {
+                let ty = extract_stream_item_ty(ty)
+                    .map_or_else(|| quote!(::serde_json::Value), |ty| quote!(#ty));
+                gen_types.push(ty.clone());
+                quote!(Some(&<#ty as TypeDef>::INFO))
+            }
             (_, true) | (None, _) => quote!(None),
             (Some(ty), false) => {
                 let ty = extract_result_ty(ty);
@@ -44,6 +54,26 @@ pub(crate) fn generate_typescript_generator(info: &RpcInfo, outdir_path: &String
         let ts_name = method.name.to_case(Case::Camel);
         let rpc_name = &method.name;
         let is_notification = method.is_notification;
+        let is_subscription = method.is_subscription;
+        let gen_error = match method.error {
+            Some(ty) => {
+                gen_types.push(quote!(#ty));
+                gen_errors.push(quote!(#ty).to_string());
+                quote!(Some(<#ty as ::yerpc::RpcError>::NAME))
+            }
+            None => quote!(None),
+        };
+        // Only requests report progress.
+        let progress = method.context.iter().find(|input| is_progress_ty(input.ty));
+        let gen_progress = match progress {
+            Some(progress) if !is_notification && !is_subscription => {
+                let ty = extract_progress_ty(progress.ty)
+                    .map_or_else(|| quote!(::serde_json::Value), |ty| quote!(#ty));
+                gen_types.push(ty.clone());
+                quote!(Some(&<#ty as TypeDef>::INFO))
+            }
+            _ => quote!(None),
+        };
         let docs = if let Some(docs) = &method.docs {
             quote!(Some(#docs))
         } else {
@@ -51,7 +81,12 @@ pub(crate) fn generate_typescript_generator(info: &RpcInfo, outdir_path: &String
         };
         gen_methods.push(quote!(
                 let args = vec![#(#gen_args),*];
-                let method = Method::new(#ts_name, #rpc_name, args, #gen_output, #is_notification, #is_positional, #docs);
+                let method = Method {
+                    is_subscription: #is_subscription,
+                    progress: #gen_progress,
+                    error: #gen_error,
+                    ..Method::new(#ts_name, #rpc_name, args, #gen_output, #is_notification, #is_positional, #docs)
+                };
                 out.push_str(&method.to_string(root_namespace));
         ));
     }
@@ -70,6 +105,9 @@ pub(crate) fn generate_typescript_generator(info: &RpcInfo, outdir_path: &String
     all_types.sort();
     all_types.dedup();
     let all_types: Vec<TokenStream> = all_types.into_iter().map(|s| s.parse().unwrap()).collect();
+    gen_errors.sort();
+    gen_errors.dedup();
+    let gen_errors: Vec<TokenStream> = gen_errors.into_iter().map(|s| s.parse().unwrap()).collect();
 
     quote! {
         /// Generate typescript bindings for the JSON-RPC API.
@@ -112,7 +150,9 @@ pub(crate) fn generate_typescript_generator(info: &RpcInfo, outdir_path: &String
             let root_namespace = Some("T");
             let mut out = String::new();
             #(#gen_methods)*
-            let ts_module = #ts_base.replace("#methods", &out);
+            let mut errors = String::new();
+            #(errors.push_str(&::yerpc::typescript::error_codes_to_string::<#gen_errors>());)*
+            let ts_module = #ts_base.replace("#methods", &out).replace("#errors", &errors);
             fs::write(&outdir.join("client.ts"), &ts_module).expect("Failed to write TS bindings");
         }
     }
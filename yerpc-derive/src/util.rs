@@ -1,4 +1,4 @@
-use syn::{GenericArgument, PathArguments, Type};
+use syn::{GenericArgument, PathArguments, Type, TypeParamBound};
 
 pub fn is_result_ty(ty: &Type) -> bool {
     if let Type::Path(path) = ty {
@@ -26,6 +26,103 @@ pub fn extract_result_ty(ty: &Type) -> &Type {
     ty
 }
 
+/// Extracts the typed error enum `E` from `Result<T, E>`.
+///
+/// Returns `None` for results without an error type argument.
+pub fn extract_rpc_error_ty(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let last = path.path.segments.last()?;
+    if last.ident != "Result" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(ref generics) = last.arguments else {
+        return None;
+    };
+    match generics.args.iter().nth(1) {
+        Some(GenericArgument::Type(error_ty)) => Some(error_ty),
+        _ => None,
+    }
+}
+
+/// Returns true if the type of a context argument is the progress reporter `Progress<T>`.
+pub fn is_progress_ty(ty: &Type) -> bool {
+    if let Type::Path(path) = ty {
+        if let Some(last) = path.path.segments.last() {
+            return last.ident == "Progress";
+        }
+    }
+    false
+}
+
+/// Extracts the type of the reported values from `Progress<T>`.
+///
+/// Returns `None` for `Progress` without type arguments.
+pub fn extract_progress_ty(ty: &Type) -> Option<&Type> {
+    if let Type::Path(path) = ty {
+        if let Some(last) = path.path.segments.last() {
+            if let PathArguments::AngleBracketed(ref generics) = last.arguments {
+                if let Some(GenericArgument::Type(inner_ty)) = generics.args.first() {
+                    return Some(inner_ty);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Extracts the item type of the stream returned by a subscription method.
+///
+/// Supports `impl Stream<Item = T>`, boxed and pinned `dyn Stream<Item = T>`
+/// and `BoxStream<'_, T>`, optionally wrapped in a `Result`.
+pub fn extract_stream_item_ty(ty: &Type) -> Option<&Type> {
+    match extract_result_ty(ty) {
+        Type::ImplTrait(ty) => extract_bound_item_ty(ty.bounds.iter()),
+        Type::TraitObject(ty) => extract_bound_item_ty(ty.bounds.iter()),
+        Type::Paren(ty) => extract_stream_item_ty(&ty.elem),
+        Type::Path(path) => {
+            let last = path.path.segments.last()?;
+            let PathArguments::AngleBracketed(ref generics) = last.arguments else {
+                return None;
+            };
+            let inner_ty = generics.args.iter().find_map(|arg| match arg {
+                GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            })?;
+            if last.ident == "Pin" || last.ident == "Box" {
+                extract_stream_item_ty(inner_ty)
+            } else if last.ident == "BoxStream" || last.ident == "LocalBoxStream" {
+                Some(inner_ty)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+fn extract_bound_item_ty<'a>(
+    mut bounds: impl Iterator<Item = &'a TypeParamBound>,
+) -> Option<&'a Type> {
+    bounds.find_map(|bound| {
+        let TypeParamBound::Trait(bound) = bound else {
+            return None;
+        };
+        let last = bound.path.segments.last()?;
+        if last.ident != "Stream" {
+            return None;
+        }
+        let PathArguments::AngleBracketed(ref generics) = last.arguments else {
+            return None;
+        };
+        generics.args.iter().find_map(|arg| match arg {
+            GenericArgument::Binding(binding) if binding.ident == "Item" => Some(&binding.ty),
+            _ => None,
+        })
+    })
+}
+
 // pub fn ty_ident(name: &str) -> Ident {
 //     Ident::new(&name.to_case(Case::UpperCamel), Span::call_site())
 // }
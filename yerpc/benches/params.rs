@@ -0,0 +1,137 @@
+//! Decoding of large params, through a parsed `Value` and straight from the raw JSON,
+//! alone and through a whole request to an `RpcServer`.
+//!
+//! Run with `cargo bench -p yerpc --bench params`.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use yerpc::{rpc, RpcClient, RpcServer, RpcSession, TypeDef};
+
+#[derive(Serialize, Deserialize, TypeDef)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
+struct Record {
+    name: String,
+    tags: Vec<String>,
+    values: Vec<f64>,
+}
+
+#[derive(Deserialize)]
+struct RawRequest<'a> {
+    #[serde(borrow)]
+    params: &'a RawValue,
+}
+
+struct Api;
+
+#[rpc(all_positional, ts_outdir = "../target/bench-bindings")]
+impl Api {
+    pub async fn store(&self, records: Vec<Record>) -> usize {
+        records.len()
+    }
+}
+
+/// The same API served through [`RpcServer::handle_request`],
+/// decoding the params from a `Value` like `#[rpc]` did before raw params.
+struct ValueApi;
+
+#[yerpc::async_trait]
+impl RpcServer for ValueApi {
+    async fn handle_request(
+        &self,
+        method: String,
+        params: serde_json::Value,
+    ) -> yerpc::Result<serde_json::Value> {
+        assert_eq!(method, "store");
+        let mut params = match params {
+            serde_json::Value::Array(params) => params.into_iter(),
+            _ => unreachable!(),
+        };
+        let records: Vec<Record> =
+            yerpc::deserialize_param(params.next().unwrap(), "records", Some(0))?;
+        Ok(serde_json::to_value(records.len())?)
+    }
+}
+
+fn request(records: usize) -> String {
+    let records: Vec<Record> = (0..records)
+        .map(|i| Record {
+            name: format!("record {i}"),
+            tags: vec!["alpha".to_string(), "beta".to_string()],
+            values: (0..16).map(|v| (i * v) as f64 / 3.0).collect(),
+        })
+        .collect();
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": "store",
+        "params": [records],
+        "id": 1,
+    })
+    .to_string()
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode_params");
+    for records in [100, 10_000] {
+        let request = request(records);
+        group.throughput(Throughput::Bytes(request.len() as u64));
+
+        group.bench_with_input(
+            BenchmarkId::new("value", records),
+            &request,
+            |b, request| {
+                b.iter(|| {
+                    let mut message: serde_json::Value = serde_json::from_str(request).unwrap();
+                    let params = message["params"].take();
+                    let mut params = match params {
+                        serde_json::Value::Array(params) => params.into_iter(),
+                        _ => unreachable!(),
+                    };
+                    let records: Vec<Record> =
+                        yerpc::deserialize_param(params.next().unwrap(), "records", Some(0))
+                            .unwrap();
+                    records.len()
+                })
+            },
+        );
+
+        group.bench_with_input(BenchmarkId::new("raw", records), &request, |b, request| {
+            b.iter(|| {
+                let message: RawRequest = serde_json::from_str(request).unwrap();
+                let params: Vec<&RawValue> = serde_json::from_str(message.params.get()).unwrap();
+                let records: Vec<Record> =
+                    yerpc::deserialize_raw_param(params.first().copied(), "records", Some(0))
+                        .unwrap();
+                records.len()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn handle_request(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let (client, _out_rx) = RpcClient::new();
+    let value_session = RpcSession::new(client, ValueApi);
+    let (client, _out_rx) = RpcClient::new();
+    let raw_session = RpcSession::new(client, Api);
+
+    let mut group = c.benchmark_group("handle_request");
+    for records in [100, 10_000] {
+        let request = request(records);
+        group.throughput(Throughput::Bytes(request.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::new("value", records),
+            &request,
+            |b, request| b.iter(|| runtime.block_on(value_session.process_incoming(request))),
+        );
+        group.bench_with_input(BenchmarkId::new("raw", records), &request, |b, request| {
+            b.iter(|| runtime.block_on(raw_session.process_incoming(request)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, decode, handle_request);
+criterion_main!(benches);
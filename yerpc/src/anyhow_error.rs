@@ -0,0 +1,199 @@
+//! Conversion of [`anyhow::Error`]s returned by handlers into JSON-RPC errors.
+//!
+//! Handlers returning [`anyhow::Result`] fail with an [`Error`] rendered by the default
+//! [`AnyhowConverter`]. While the session runs a handler, it also records the original
+//! `anyhow` errors converted on the way, and renders the one the handler failed with
+//! again with its own converter, set with
+//! [`RpcSession::with_anyhow_converter`](crate::RpcSession::with_anyhow_converter).
+use std::backtrace::BacktraceStatus;
+use std::cell::RefCell;
+use std::future::{poll_fn, Future};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{Map, Value};
+
+use crate::Error;
+
+/// How much of an `anyhow` error is put in the error message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnyhowMessage {
+    /// `INTERNAL_ERROR` with the message "Internal server error".
+    Hidden,
+
+    /// Code -1 with the top-level message, without the context chain.
+    TopLevel,
+
+    /// Code -1 with the full `{:?}` representation,
+    /// including the context chain and backtrace.
+    Debug,
+}
+
+/// Policy rendering `anyhow` errors returned by handlers.
+///
+/// The default policy hides the errors, or exposes their `{:?}` representation
+/// if the `anyhow_expose` feature is enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnyhowConverter {
+    message: AnyhowMessage,
+    chain: bool,
+    backtrace: bool,
+    correlation_id: bool,
+}
+
+impl Default for AnyhowConverter {
+    fn default() -> Self {
+        match cfg!(feature = "anyhow_expose") {
+            true => Self::new(AnyhowMessage::Debug),
+            false => Self::new(AnyhowMessage::Hidden),
+        }
+    }
+}
+
+impl AnyhowConverter {
+    /// Creates a policy putting `message` in the error message and nothing in `data`.
+    pub fn new(message: AnyhowMessage) -> Self {
+        Self {
+            message,
+            chain: false,
+            backtrace: false,
+            correlation_id: false,
+        }
+    }
+
+    /// Puts the context chain, outermost first, in the `chain` array of the error data.
+    pub fn with_chain(mut self) -> Self {
+        self.chain = true;
+        self
+    }
+
+    /// Puts the backtrace in the `backtrace` field of the error data.
+    ///
+    /// Only has an effect in debug builds and if the backtrace was captured,
+    /// see [`std::backtrace`] for how backtrace capture is enabled.
+    pub fn with_backtrace(mut self) -> Self {
+        self.backtrace = true;
+        self
+    }
+
+    /// Puts a unique ID in the `correlationId` field of the error data
+    /// and logs the full error with it, so hidden errors can be traced
+    /// from the client side.
+    pub fn with_correlation_id(mut self) -> Self {
+        self.correlation_id = true;
+        self
+    }
+
+    /// Converts `error` to a JSON-RPC error according to the policy.
+    pub fn convert(&self, error: &anyhow::Error) -> Error {
+        let (code, message) = match self.message {
+            AnyhowMessage::Hidden => (Error::INTERNAL_ERROR, "Internal server error".to_string()),
+            AnyhowMessage::TopLevel => (-1, error.to_string()),
+            AnyhowMessage::Debug => (-1, format!("{error:?}")),
+        };
+
+        let mut data = Map::new();
+        if self.chain {
+            let chain = error
+                .chain()
+                .map(|cause| cause.to_string().into())
+                .collect();
+            data.insert("chain".to_string(), Value::Array(chain));
+        }
+        if self.backtrace
+            && cfg!(debug_assertions)
+            && error.backtrace().status() == BacktraceStatus::Captured
+        {
+            data.insert(
+                "backtrace".to_string(),
+                error.backtrace().to_string().into(),
+            );
+        }
+        if self.correlation_id {
+            let id = correlation_id();
+            log::error!("JSON-RPC handler failed (correlation ID {id}): {error:?}");
+            data.insert("correlationId".to_string(), id.into());
+        }
+
+        let data = (!data.is_empty()).then_some(Value::Object(data));
+        Error::with_data(code, message, data)
+    }
+
+    /// Renders `error` again if it was converted from one of the `captured` errors.
+    pub(crate) fn apply(&self, error: Error, captured: &Captured) -> Error {
+        let original = captured
+            .iter()
+            .rev()
+            .find(|(converted, _)| same_error(converted, &error));
+        match original {
+            Some((_, anyhow)) => self.convert(anyhow),
+            None => error,
+        }
+    }
+}
+
+/// `anyhow` errors converted while running a handler,
+/// with their default rendering.
+pub(crate) type Captured = Vec<(Error, anyhow::Error)>;
+
+thread_local! {
+    /// Errors converted while polling a future passed to [`capture`].
+    static CAPTURED: RefCell<Option<Captured>> = const { RefCell::new(None) };
+}
+
+/// Runs `fut`, recording the `anyhow` errors converted while it is polled.
+pub(crate) async fn capture<F: Future>(fut: F) -> (F::Output, Captured) {
+    /// Restores the errors recorded by an outer future, even on panic.
+    struct Restore(Option<Captured>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            CAPTURED.with(|captured| *captured.borrow_mut() = self.0.take());
+        }
+    }
+
+    let mut fut = std::pin::pin!(fut);
+    let mut captured = Some(Captured::new());
+    let output = poll_fn(|cx| {
+        let outer = CAPTURED.with(|slot| slot.replace(captured.take()));
+        let restore = Restore(outer);
+        let res = fut.as_mut().poll(cx);
+        captured = CAPTURED.with(|slot| slot.take());
+        drop(restore);
+        res
+    })
+    .await;
+    (output, captured.unwrap_or_default())
+}
+
+/// Returns whether both errors have the same content.
+fn same_error(a: &Error, b: &Error) -> bool {
+    a.code == b.code && a.message == b.message && a.data == b.data
+}
+
+/// Returns an ID unique within the process and unlikely to repeat across restarts.
+fn correlation_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis());
+    format!("{timestamp:x}-{count:x}")
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(error: anyhow::Error) -> Self {
+        let converted = AnyhowConverter::default().convert(&error);
+        CAPTURED.with(|captured| {
+            if let Some(captured) = captured.borrow_mut().as_mut() {
+                let copy = Error::with_data(
+                    converted.code,
+                    converted.message.clone(),
+                    converted.data.clone(),
+                );
+                captured.push((copy, error));
+            }
+        });
+        converted
+    }
+}
@@ -0,0 +1,111 @@
+//! Encodings of messages on the wire.
+//!
+//! Sessions and clients work with JSON text. A [`Codec`] converts it to and from
+//! the frames of a transport, for peers that expect another encoding on the wire.
+//! [`Json`] is the default and is sent in text frames,
+//! codecs returning true from [`Codec::is_binary`] are sent in binary frames.
+//!
+//! WebSocket integrations negotiate the codec with the `Sec-WebSocket-Protocol`
+//! header. Peers that do not ask for a subprotocol get [`Json`].
+use std::sync::Arc;
+
+use crate::Error;
+
+/// Encoding of messages on the wire.
+pub trait Codec: Send + Sync + 'static {
+    /// WebSocket subprotocol announcing the codec.
+    fn subprotocol(&self) -> &'static str;
+
+    /// Returns true if frames are binary, false if they are UTF-8 text.
+    fn is_binary(&self) -> bool;
+
+    /// Encodes a serialized JSON message into a frame.
+    ///
+    /// Fails with [`Error::INTERNAL_ERROR`].
+    fn encode(&self, message: String) -> Result<Vec<u8>, Error>;
+
+    /// Decodes a frame into a serialized JSON message.
+    ///
+    /// Fails with [`Error::PARSE_ERROR`].
+    fn decode(&self, frame: Vec<u8>) -> Result<String, Error>;
+}
+
+impl<C: Codec + ?Sized> Codec for Arc<C> {
+    fn subprotocol(&self) -> &'static str {
+        (**self).subprotocol()
+    }
+
+    fn is_binary(&self) -> bool {
+        (**self).is_binary()
+    }
+
+    fn encode(&self, message: String) -> Result<Vec<u8>, Error> {
+        (**self).encode(message)
+    }
+
+    fn decode(&self, frame: Vec<u8>) -> Result<String, Error> {
+        (**self).decode(frame)
+    }
+}
+
+/// JSON text, the default codec.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+impl Codec for Json {
+    fn subprotocol(&self) -> &'static str {
+        "jsonrpc"
+    }
+
+    fn is_binary(&self) -> bool {
+        false
+    }
+
+    fn encode(&self, message: String) -> Result<Vec<u8>, Error> {
+        Ok(message.into_bytes())
+    }
+
+    fn decode(&self, frame: Vec<u8>) -> Result<String, Error> {
+        String::from_utf8(frame).map_err(|err| Error::new(Error::PARSE_ERROR, err.to_string()))
+    }
+}
+
+/// Returns `preferred` followed by [`Json`], the fallback of all connections.
+pub fn supported(preferred: &Arc<dyn Codec>) -> Vec<Arc<dyn Codec>> {
+    let mut codecs = vec![preferred.clone()];
+    if preferred.subprotocol() != Json.subprotocol() {
+        codecs.push(Arc::new(Json));
+    }
+    codecs
+}
+
+/// Returns the built-in codec announced by `subprotocol`.
+pub fn from_subprotocol(subprotocol: &str) -> Option<Arc<dyn Codec>> {
+    match subprotocol.trim() == Json.subprotocol() {
+        true => Some(Arc::new(Json)),
+        false => None,
+    }
+}
+
+/// Returns the value of the `Sec-WebSocket-Protocol` header
+/// offering `preferred` and [`Json`].
+pub fn offer(preferred: &Arc<dyn Codec>) -> String {
+    supported(preferred)
+        .iter()
+        .map(|codec| codec.subprotocol())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Chooses the codec of a connection from the subprotocols offered by the peer,
+/// the value of its `Sec-WebSocket-Protocol` header.
+///
+/// Picks `preferred` if it is offered, otherwise [`Json`] if it is offered.
+/// Returns `None` if neither is offered.
+pub fn negotiate(offered: &str, preferred: &Arc<dyn Codec>) -> Option<Arc<dyn Codec>> {
+    let offered: Vec<&str> = offered.split(',').map(str::trim).collect();
+    if offered.contains(&preferred.subprotocol()) {
+        return Some(preferred.clone());
+    }
+    offered.into_iter().find_map(from_subprotocol)
+}
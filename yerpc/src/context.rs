@@ -0,0 +1,167 @@
+//! Per-request context passed to handlers.
+//!
+//! Methods of an `#[rpc]` server can take extractor arguments
+//! that are filled from the [`RequestContext`] instead of the request params,
+//! similar to axum extractors. Extractor arguments are marked with `#[rpc(context)]`
+//! and implement [`FromContext`], such as [`RequestId`], [`MethodName`],
+//! [`RpcClient`], [`Meta`] and [`Progress`].
+//! They are not part of the generated TypeScript and OpenRPC signatures.
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::{Error, Id, Progress, Result, RpcClient};
+
+/// Connection metadata attached to a session,
+/// such as the peer address or HTTP headers.
+///
+/// Holds at most one value of each type.
+#[derive(Clone, Default)]
+pub(crate) struct Metadata(Arc<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>);
+
+impl Metadata {
+    pub(crate) fn insert<T: Send + Sync + 'static>(&mut self, value: T) {
+        Arc::make_mut(&mut self.0).insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    fn get<T: 'static>(&self) -> Option<&T> {
+        self.0.get(&TypeId::of::<T>())?.downcast_ref()
+    }
+}
+
+/// Data about the request being handled.
+#[derive(Clone, Default)]
+pub struct RequestContext {
+    id: Option<Id>,
+    method: String,
+    client: Option<RpcClient>,
+    metadata: Metadata,
+}
+
+impl RequestContext {
+    /// Creates the context of a call to `method` outside of a session.
+    ///
+    /// Used by the code generated with the [`rpc`](crate::rpc) macro
+    /// when a handler is called directly.
+    pub fn new(method: impl Into<String>) -> Self {
+        Self {
+            method: method.into(),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn in_session(
+        id: Option<Id>,
+        method: String,
+        client: RpcClient,
+        metadata: Metadata,
+    ) -> Self {
+        Self {
+            id,
+            method,
+            client: Some(client),
+            metadata,
+        }
+    }
+
+    /// Returns the request ID, `None` for notifications.
+    pub fn id(&self) -> Option<&Id> {
+        self.id.as_ref()
+    }
+
+    /// Returns the method name.
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    /// Returns the client of the session the request arrived on.
+    ///
+    /// Returns `None` if the handler is called outside of a session.
+    pub fn client(&self) -> Option<&RpcClient> {
+        self.client.as_ref()
+    }
+
+    /// Returns the connection metadata of type `T`
+    /// attached with [`RpcSession::with_metadata`](crate::RpcSession::with_metadata).
+    pub fn metadata<T: 'static>(&self) -> Option<&T> {
+        self.metadata.get()
+    }
+}
+
+impl std::fmt::Debug for RequestContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RequestContext")
+            .field("id", &self.id)
+            .field("method", &self.method)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Types that can be extracted from the request context.
+pub trait FromContext: Sized {
+    /// Extracts the value, failing the request if it is not available.
+    fn from_context(context: &RequestContext) -> Result<Self>;
+}
+
+/// Extractor of the request ID.
+///
+/// Fails for notifications.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub Id);
+
+impl FromContext for RequestId {
+    fn from_context(context: &RequestContext) -> Result<Self> {
+        let id = context
+            .id()
+            .ok_or_else(|| Error::internal("Notifications have no request ID".to_string()))?;
+        Ok(Self(id.clone()))
+    }
+}
+
+/// Extractor of the method name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodName(pub String);
+
+impl FromContext for MethodName {
+    fn from_context(context: &RequestContext) -> Result<Self> {
+        Ok(Self(context.method().to_string()))
+    }
+}
+
+impl FromContext for RpcClient {
+    fn from_context(context: &RequestContext) -> Result<Self> {
+        context
+            .client()
+            .cloned()
+            .ok_or_else(|| Error::internal("No client outside of a session".to_string()))
+    }
+}
+
+/// Extractor of connection metadata of type `T`.
+///
+/// Fails if no such metadata is attached to the session.
+#[derive(Debug, Clone)]
+pub struct Meta<T>(pub T);
+
+impl<T: Clone + 'static> FromContext for Meta<T> {
+    fn from_context(context: &RequestContext) -> Result<Self> {
+        let value = context.metadata::<T>().ok_or_else(|| {
+            Error::internal(format!(
+                "Missing connection metadata `{}`",
+                type_name::<T>()
+            ))
+        })?;
+        Ok(Self(value.clone()))
+    }
+}
+
+impl<T> FromContext for Progress<T> {
+    /// Reports progress of the request,
+    /// or discards it for notifications and outside of a session.
+    fn from_context(context: &RequestContext) -> Result<Self> {
+        Ok(match (context.client(), context.id()) {
+            (Some(client), Some(id)) => Progress::new(client.clone(), id.clone()),
+            _ => Progress::default(),
+        })
+    }
+}
@@ -1,55 +1,99 @@
+use crate::codec::{self, Codec, Json};
+use crate::transport::{serve_frames, Frame};
 use crate::{OutReceiver, RpcServer, RpcSession};
 use axum::{
-    extract::ws::{Message, WebSocket, WebSocketUpgrade},
+    async_trait,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, FromRequestParts,
+    },
+    http::{request::Parts, HeaderMap},
     response::Response,
 };
-use futures_util::{SinkExt, StreamExt};
-use tokio::task::JoinHandle;
+use futures_util::{future::ready, SinkExt, StreamExt};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
 
+/// Connection metadata captured from the WebSocket upgrade request.
+///
+/// Attach it to the session with [`RpcSession::with_metadata`]
+/// to make it available to handlers as [`Meta<ConnectionInfo>`](crate::Meta).
+#[derive(Debug, Clone)]
+pub struct ConnectionInfo {
+    /// Address of the peer,
+    /// if the app is served with `into_make_service_with_connect_info`.
+    pub peer_addr: Option<SocketAddr>,
+
+    /// Headers of the upgrade request.
+    pub headers: HeaderMap,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ConnectionInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let peer_addr = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| *addr);
+        Ok(Self {
+            peer_addr,
+            headers: parts.headers.clone(),
+        })
+    }
+}
+
+/// Upgrades the connection to a WebSocket served by `session`.
+///
+/// The codec is negotiated with the `Sec-WebSocket-Protocol` header:
+/// axum selects the codec of the session or [`Json`] if the peer offers it,
+/// see [`WebSocketUpgrade::protocols`]. Peers that offer none get [`Json`].
 pub async fn handle_ws_rpc<T: RpcServer>(
     ws: WebSocketUpgrade,
     out_rx: OutReceiver,
     session: RpcSession<T>,
 ) -> Response {
-    ws.on_upgrade(move |socket| async move {
-        match handle_rpc(socket, out_rx, session).await {
-            Ok(()) => {}
-            Err(err) => tracing::warn!("yerpc websocket closed with error {err:?}"),
-        }
-    })
+    let protocols: Vec<&'static str> = codec::supported(session.client().codec())
+        .iter()
+        .map(|codec| codec.subprotocol())
+        .collect();
+    ws.protocols(protocols)
+        .on_upgrade(move |socket| async move {
+            match handle_rpc(socket, out_rx, session).await {
+                Ok(()) => {}
+                Err(err) => tracing::warn!("yerpc websocket closed with error {err:?}"),
+            }
+        })
 }
 
+/// Serves `session` over an upgraded WebSocket with [`serve_frames`].
 pub async fn handle_rpc<T: RpcServer>(
     socket: WebSocket,
-    mut out_rx: OutReceiver,
+    out_rx: OutReceiver,
     session: RpcSession<T>,
 ) -> anyhow::Result<()> {
-    let (mut sender, mut receiver) = socket.split();
-    let send_task: JoinHandle<anyhow::Result<()>> = tokio::spawn(async move {
-        while let Some(message) = out_rx.next().await {
-            let message = serde_json::to_string(&message)?;
-            tracing::trace!("RPC send {}", message);
-            sender.send(Message::Text(message)).await?;
-        }
-        Ok(())
+    let codec: Arc<dyn Codec> = socket
+        .protocol()
+        .and_then(|protocol| protocol.to_str().ok())
+        .and_then(|protocol| codec::negotiate(protocol, session.client().codec()))
+        .unwrap_or_else(|| Arc::new(Json));
+    let (sender, receiver) = socket.split();
+    let incoming = receiver.filter_map(|message| {
+        ready(match message {
+            Ok(Message::Text(message)) => Some(Ok(Frame::Text(message))),
+            Ok(Message::Binary(frame)) => Some(Ok(Frame::Binary(frame))),
+            Ok(_) => None,
+            Err(err) => Some(Err(err)),
+        })
     });
-    let recv_task: JoinHandle<anyhow::Result<()>> = tokio::spawn(async move {
-        while let Some(message) = receiver.next().await {
-            match message {
-                Ok(Message::Text(message)) => {
-                    tracing::trace!("RPC recv {}", message);
-                    session.handle_incoming(&message).await;
-                }
-                Ok(Message::Binary(_)) => {
-                    return Err(anyhow::anyhow!("Binary messages are not supported."))
-                }
-                Ok(_) => {}
-                Err(err) => return Err(anyhow::anyhow!(err)),
-            }
-        }
-        Ok(())
+    let outgoing = sender.with(|frame| {
+        ready(Ok::<_, axum::Error>(match frame {
+            Frame::Text(message) => Message::Text(message),
+            Frame::Binary(frame) => Message::Binary(frame),
+        }))
     });
-    recv_task.await??;
-    send_task.await??;
+    serve_frames(session, out_rx, &*codec, incoming, outgoing).await?;
     Ok(())
 }
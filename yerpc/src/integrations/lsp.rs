@@ -0,0 +1,360 @@
+//! Messages framed with `Content-Length` headers over byte streams,
+//! the base protocol of the Language Server Protocol and the Debug Adapter Protocol.
+//!
+//! Every message is preceded by a header of `Name: value` fields separated by `\r\n`
+//! and terminated by an empty line:
+//!
+//! ```text
+//! Content-Length: 40\r\n
+//! \r\n
+//! {"jsonrpc":"2.0","method":"ping","id":1}
+//! ```
+//!
+//! `Content-Length` is required, `Content-Type` may only announce UTF-8
+//! and other fields are ignored. Frames with an unsupported `Content-Type`
+//! or an invalid body are skipped and answered with [`Error::PARSE_ERROR`].
+//! Without a valid `Content-Length` the end of the frame is unknown,
+//! so such a header ends the connection.
+use crate::transport::{serve, Received, TransportError};
+use crate::{Error, OutReceiver, RpcServer, RpcSession};
+use futures_util::{sink, stream};
+use std::fmt;
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Default maximum length of a message body in bytes, 16 MiB.
+pub const DEFAULT_MAX_CONTENT_LENGTH: usize = 16 * 1024 * 1024;
+
+/// Maximum length of a header in bytes.
+pub const MAX_HEADER_LENGTH: usize = 8 * 1024;
+
+/// Error of a frame that cannot be decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameError {
+    /// Header is not terminated within [`MAX_HEADER_LENGTH`] bytes.
+    HeaderTooLong,
+
+    /// Header field is not a `Name: value` pair or is not valid UTF-8.
+    InvalidHeader(String),
+
+    /// `Content-Length` is missing.
+    MissingContentLength,
+
+    /// `Content-Length` is not a number or given more than once.
+    InvalidContentLength(String),
+
+    /// `Content-Type` announces a charset other than UTF-8.
+    UnsupportedContentType(String),
+
+    /// Body is longer than the maximum content length.
+    TooLarge(usize),
+
+    /// Body is not valid UTF-8.
+    InvalidUtf8,
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::HeaderTooLong => write!(f, "Header exceeds {MAX_HEADER_LENGTH} bytes."),
+            FrameError::InvalidHeader(field) => write!(f, "Invalid header field {field:?}."),
+            FrameError::MissingContentLength => write!(f, "Missing Content-Length header."),
+            FrameError::InvalidContentLength(value) => {
+                write!(f, "Invalid Content-Length {value:?}.")
+            }
+            FrameError::UnsupportedContentType(value) => {
+                write!(f, "Unsupported Content-Type {value:?}.")
+            }
+            FrameError::TooLarge(length) => {
+                write!(f, "Content-Length {length} exceeds the maximum length.")
+            }
+            FrameError::InvalidUtf8 => write!(f, "Message is not valid UTF-8."),
+        }
+    }
+}
+
+impl FrameError {
+    /// Returns true if the end of the invalid frame is unknown,
+    /// so no further frames can be decoded.
+    pub fn is_fatal(&self) -> bool {
+        match self {
+            FrameError::HeaderTooLong
+            | FrameError::InvalidHeader(_)
+            | FrameError::MissingContentLength
+            | FrameError::InvalidContentLength(_) => true,
+            FrameError::UnsupportedContentType(_)
+            | FrameError::TooLarge(_)
+            | FrameError::InvalidUtf8 => false,
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+/// Encodes a message into a frame.
+pub fn encode_frame(message: &str) -> Vec<u8> {
+    let mut frame = format!("Content-Length: {}\r\n\r\n", message.len()).into_bytes();
+    frame.extend_from_slice(message.as_bytes());
+    frame
+}
+
+/// Incremental decoder of frames.
+///
+/// Bytes are appended to a buffer as they are read
+/// and complete frames are taken out of it with [`FrameDecoder::decode`].
+#[derive(Debug, Clone)]
+pub struct FrameDecoder {
+    max_content_length: usize,
+    state: State,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum State {
+    /// Waiting for a complete header.
+    Header,
+
+    /// Waiting for a body of the given length.
+    Body(usize),
+
+    /// Discarding the given number of bytes of a rejected body.
+    Skip(usize),
+
+    /// Stopped by a fatal error, which is returned again.
+    Failed(FrameError),
+}
+
+impl FrameDecoder {
+    /// Creates a decoder rejecting bodies longer than `max_content_length` bytes.
+    pub fn new(max_content_length: usize) -> Self {
+        Self {
+            max_content_length,
+            state: State::Header,
+        }
+    }
+
+    /// Takes the next message out of `buf`.
+    ///
+    /// Returns `Ok(None)` if more bytes are needed.
+    /// After an error the invalid frame is skipped,
+    /// call it again to decode the following frames.
+    /// Fatal errors, see [`FrameError::is_fatal`], are returned by all further calls.
+    pub fn decode(&mut self, buf: &mut Vec<u8>) -> Result<Option<String>, FrameError> {
+        loop {
+            match self.state {
+                State::Header => {
+                    let Some(end) = find(buf, b"\r\n\r\n") else {
+                        if buf.len() > MAX_HEADER_LENGTH {
+                            return Err(self.fail(FrameError::HeaderTooLong));
+                        }
+                        return Ok(None);
+                    };
+                    let header = parse_header(&buf[..end]);
+                    match header {
+                        Ok(length) => {
+                            buf.drain(..end + 4);
+                            if length > self.max_content_length {
+                                self.state = State::Skip(length);
+                                return Err(FrameError::TooLarge(length));
+                            }
+                            self.state = State::Body(length);
+                        }
+                        Err((Some(length), err)) => {
+                            buf.drain(..end + 4);
+                            self.state = State::Skip(length);
+                            return Err(err);
+                        }
+                        // The length of the body is unknown.
+                        Err((None, err)) => return Err(self.fail(err)),
+                    }
+                }
+                State::Body(length) => {
+                    if buf.len() < length {
+                        return Ok(None);
+                    }
+                    let body: Vec<u8> = buf.drain(..length).collect();
+                    self.state = State::Header;
+                    return String::from_utf8(body)
+                        .map(Some)
+                        .map_err(|_| FrameError::InvalidUtf8);
+                }
+                State::Skip(length) => {
+                    let skipped = length.min(buf.len());
+                    buf.drain(..skipped);
+                    if skipped < length {
+                        self.state = State::Skip(length - skipped);
+                        return Ok(None);
+                    }
+                    self.state = State::Header;
+                }
+                State::Failed(ref err) => return Err(err.clone()),
+            }
+        }
+    }
+
+    /// Stops decoding after the fatal error `err`.
+    fn fail(&mut self, err: FrameError) -> FrameError {
+        self.state = State::Failed(err.clone());
+        err
+    }
+
+    /// Returns true if `buf` ends within a frame.
+    fn is_truncated(&self, buf: &[u8]) -> bool {
+        match self.state {
+            State::Header => !buf.is_empty(),
+            State::Body(_) | State::Skip(_) => true,
+            // The error is reported already.
+            State::Failed(_) => false,
+        }
+    }
+}
+
+/// Parses a header, returning the content length.
+///
+/// Errors carry the content length if the body can be skipped.
+fn parse_header(header: &[u8]) -> Result<usize, (Option<usize>, FrameError)> {
+    let header = std::str::from_utf8(header).map_err(|_| {
+        let header = String::from_utf8_lossy(header).into_owned();
+        (None, FrameError::InvalidHeader(header))
+    })?;
+    let mut content_length = None;
+    let mut content_type = None;
+    for field in header.split("\r\n") {
+        let Some((name, value)) = field.split_once(':') else {
+            return Err((None, FrameError::InvalidHeader(field.to_string())));
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("Content-Length") {
+            let length = match (content_length, value.parse::<usize>()) {
+                (None, Ok(length)) if value.bytes().all(|byte| byte.is_ascii_digit()) => length,
+                _ => return Err((None, FrameError::InvalidContentLength(value.to_string()))),
+            };
+            content_length = Some(length);
+        } else if name.eq_ignore_ascii_case("Content-Type") {
+            content_type = Some(value);
+        }
+    }
+    let Some(length) = content_length else {
+        return Err((None, FrameError::MissingContentLength));
+    };
+    if let Some(content_type) = content_type {
+        let utf8 = content_type
+            .split(';')
+            .skip(1)
+            .filter_map(|param| param.split_once('='))
+            .filter(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+            .all(|(_, charset)| {
+                let charset = charset.trim().trim_matches('"');
+                charset.eq_ignore_ascii_case("utf-8") || charset.eq_ignore_ascii_case("utf8")
+            });
+        if !utf8 {
+            let err = FrameError::UnsupportedContentType(content_type.to_string());
+            return Err((Some(length), err));
+        }
+    }
+    Ok(length)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Serves `session` over the standard input and output of the process.
+///
+/// Returns once the standard input is closed
+/// and the responses to all requests read from it are written.
+pub async fn serve_stdio<T: RpcServer>(
+    session: RpcSession<T>,
+    out_rx: OutReceiver,
+) -> Result<(), TransportError<io::Error, io::Error>> {
+    serve_framed(
+        session,
+        out_rx,
+        tokio::io::stdin(),
+        tokio::io::stdout(),
+        DEFAULT_MAX_CONTENT_LENGTH,
+    )
+    .await
+}
+
+/// Serves `session` with frames read from `reader`
+/// and written to `writer`, see [`serve`].
+///
+/// Bodies longer than `max_content_length` bytes are discarded without being buffered.
+/// Fails with [`io::ErrorKind::UnexpectedEof`] if `reader` ends within a frame
+/// and with [`io::ErrorKind::InvalidData`] on a fatal [`FrameError`].
+pub async fn serve_framed<T, R, W>(
+    session: RpcSession<T>,
+    out_rx: OutReceiver,
+    reader: R,
+    writer: W,
+    max_content_length: usize,
+) -> Result<(), TransportError<io::Error, io::Error>>
+where
+    T: RpcServer,
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let frames = Frames {
+        reader,
+        buf: Vec::new(),
+        chunk: vec![0; 8 * 1024],
+        decoder: FrameDecoder::new(max_content_length),
+        eof: false,
+    };
+    let incoming = stream::unfold(frames, |mut frames| async move {
+        let message = frames.next().await?;
+        Some((message, frames))
+    });
+    let outgoing = sink::unfold(writer, |mut writer, message: String| async move {
+        writer.write_all(&encode_frame(&message)).await?;
+        writer.flush().await?;
+        Ok::<_, io::Error>(writer)
+    });
+    serve(session, out_rx, incoming, outgoing).await
+}
+
+/// Reader of framed messages.
+struct Frames<R> {
+    reader: R,
+    /// Bytes read but not decoded yet.
+    buf: Vec<u8>,
+    /// Buffer of a single read.
+    chunk: Vec<u8>,
+    decoder: FrameDecoder,
+    eof: bool,
+}
+
+impl<R: AsyncRead + Unpin> Frames<R> {
+    /// Reads the next message, `None` at EOF.
+    ///
+    /// Frames that cannot be decoded are returned as [`Received::Invalid`].
+    async fn next(&mut self) -> Option<io::Result<Received>> {
+        loop {
+            match self.decoder.decode(&mut self.buf) {
+                Ok(Some(message)) => return Some(Ok(message.into())),
+                Ok(None) => {}
+                Err(err) if err.is_fatal() => {
+                    return Some(Err(io::Error::new(io::ErrorKind::InvalidData, err)));
+                }
+                Err(err) => {
+                    return Some(Ok(Error::new(Error::PARSE_ERROR, err.to_string()).into()));
+                }
+            }
+            if self.eof {
+                if self.decoder.is_truncated(&self.buf) {
+                    let err = io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated frame.");
+                    return Some(Err(err));
+                }
+                return None;
+            }
+            match self.reader.read(&mut self.chunk).await {
+                Ok(0) => self.eof = true,
+                Ok(read) => self.buf.extend_from_slice(&self.chunk[..read]),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
@@ -3,3 +3,9 @@ pub mod axum;
 
 #[cfg(feature = "support-tungstenite")]
 pub mod tungstenite;
+
+#[cfg(feature = "support-stdio")]
+pub mod stdio;
+
+#[cfg(feature = "support-lsp")]
+pub mod lsp;
@@ -0,0 +1,166 @@
+//! Newline-delimited JSON over byte streams, such as the standard input and output.
+//!
+//! Every message is a single line of JSON terminated by `\n`.
+//! Blank lines are skipped and a trailing `\r` is removed.
+//! Lines longer than the limit or not valid UTF-8 are skipped
+//! and answered with [`Error::PARSE_ERROR`].
+use crate::transport::{serve, Received, TransportError};
+use crate::{Error, OutReceiver, RpcServer, RpcSession};
+use futures_util::{sink, stream};
+use std::io;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+
+/// Default maximum length of a line in bytes, 16 MiB.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 16 * 1024 * 1024;
+
+/// Serves `session` over the standard input and output of the process.
+///
+/// Returns once the standard input is closed
+/// and the responses to all requests read from it are written.
+pub async fn serve_stdio<T: RpcServer>(
+    session: RpcSession<T>,
+    out_rx: OutReceiver,
+) -> Result<(), TransportError<io::Error, io::Error>> {
+    serve_lines(
+        session,
+        out_rx,
+        tokio::io::stdin(),
+        tokio::io::stdout(),
+        DEFAULT_MAX_LINE_LENGTH,
+    )
+    .await
+}
+
+/// Serves `session` with newline-delimited JSON read from `reader`
+/// and written to `writer`, see [`serve`].
+///
+/// Lines longer than `max_line_length` bytes, not counting the line terminator,
+/// are discarded without being buffered.
+/// A last line without terminator is processed once `reader` reaches EOF.
+pub async fn serve_lines<T, R, W>(
+    session: RpcSession<T>,
+    out_rx: OutReceiver,
+    reader: R,
+    writer: W,
+    max_line_length: usize,
+) -> Result<(), TransportError<io::Error, io::Error>>
+where
+    T: RpcServer,
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let lines = Lines {
+        reader: BufReader::new(reader),
+        max_line_length,
+    };
+    let incoming = stream::unfold(lines, |mut lines| async move {
+        let line = lines.next().await?;
+        Some((line, lines))
+    });
+    let outgoing = sink::unfold(writer, |mut writer, message: String| async move {
+        let message = single_line(message)?;
+        writer.write_all(message.as_bytes()).await?;
+        writer.write_all(b"\n").await?;
+        writer.flush().await?;
+        Ok::<_, io::Error>(writer)
+    });
+    serve(session, out_rx, incoming, outgoing).await
+}
+
+/// Makes sure that `message` fits on a single line.
+///
+/// Raw JSON returned by custom [`RpcServer::handle_request_raw`] implementations
+/// or middlewares may be pretty-printed, such messages are serialized again.
+fn single_line(message: String) -> io::Result<String> {
+    if !message.contains(['\n', '\r']) {
+        return Ok(message);
+    }
+    let value: serde_json::Value = serde_json::from_str(&message)?;
+    Ok(serde_json::to_string(&value)?)
+}
+
+/// Reader of newline-delimited messages.
+struct Lines<R> {
+    reader: BufReader<R>,
+    max_line_length: usize,
+}
+
+/// Line read by [`Lines`].
+enum Line {
+    /// Content of the line without terminator.
+    Complete(Vec<u8>),
+
+    /// Line exceeding the maximum length, discarded.
+    TooLong,
+}
+
+impl<R: AsyncRead + Unpin> Lines<R> {
+    /// Reads the next message, `None` at EOF.
+    ///
+    /// Lines that cannot be read are returned as [`Received::Invalid`].
+    async fn next(&mut self) -> Option<io::Result<Received>> {
+        loop {
+            let mut line = match self.read_line().await {
+                Ok(Some(Line::Complete(line))) => line,
+                Ok(Some(Line::TooLong)) => {
+                    let message = format!(
+                        "Message exceeds the maximum length of {} bytes.",
+                        self.max_line_length
+                    );
+                    return Some(Ok(Error::new(Error::PARSE_ERROR, message).into()));
+                }
+                Ok(None) => return None,
+                Err(err) => return Some(Err(err)),
+            };
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            match String::from_utf8(line) {
+                Ok(line) if line.trim().is_empty() => {}
+                Ok(line) => return Some(Ok(line.into())),
+                Err(err) => {
+                    return Some(Ok(Error::new(Error::PARSE_ERROR, err.to_string()).into()));
+                }
+            }
+        }
+    }
+
+    /// Reads the next line, `None` at EOF.
+    ///
+    /// Reads as many times as needed to find the line terminator.
+    async fn read_line(&mut self) -> io::Result<Option<Line>> {
+        let mut line = Vec::new();
+        let mut too_long = false;
+        let mut empty = true;
+        loop {
+            let buf = self.reader.fill_buf().await?;
+            if buf.is_empty() {
+                if empty {
+                    return Ok(None);
+                }
+                break;
+            }
+            empty = false;
+            let (chunk, consumed) = match buf.iter().position(|&byte| byte == b'\n') {
+                Some(end) => (&buf[..end], end + 1),
+                None => (buf, buf.len()),
+            };
+            let terminated = consumed > chunk.len();
+            // Keep reading until the terminator, but without buffering.
+            too_long |= line.len() + chunk.len() > self.max_line_length;
+            if too_long {
+                line.clear();
+            } else {
+                line.extend_from_slice(chunk);
+            }
+            self.reader.consume(consumed);
+            if terminated {
+                break;
+            }
+        }
+        Ok(Some(match too_long {
+            true => Line::TooLong,
+            false => Line::Complete(line),
+        }))
+    }
+}
@@ -1,10 +1,20 @@
+use crate::codec::{self, Codec, Json};
+use crate::transport::{serve_frames, Frame};
 use crate::{OutReceiver, RpcClient, RpcServer, RpcSession};
-use futures_util::{SinkExt, StreamExt};
+use futures_util::{future::ready, SinkExt, StreamExt};
+use std::sync::Arc;
 use tokio::{
     io::{AsyncRead, AsyncWrite},
     sync::oneshot,
 };
-use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
+use tokio_tungstenite::{
+    tungstenite::{
+        self,
+        handshake::server::{ErrorResponse, Request, Response},
+        http, Message,
+    },
+    WebSocketStream,
+};
 
 pub fn tungstenite_client<R, S>(
     stream: WebSocketStream<S>,
@@ -14,7 +24,22 @@ where
     R: RpcServer,
     S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
 {
-    let (client, out_rx) = RpcClient::new();
+    tungstenite_client_with_codec(stream, service, Json)
+}
+
+/// Like [`tungstenite_client`], exchanging messages encoded with `codec`.
+///
+/// Pass the codec chosen by the server during the handshake, see [`negotiated_codec`].
+pub fn tungstenite_client_with_codec<R, S>(
+    stream: WebSocketStream<S>,
+    service: R,
+    codec: impl Codec,
+) -> (RpcClient, oneshot::Receiver<anyhow::Result<()>>)
+where
+    R: RpcServer,
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (client, out_rx) = RpcClient::builder().codec(codec).build();
     let session = RpcSession::new(client.clone(), service);
     let (tx, rx) = oneshot::channel();
     tokio::spawn(async move {
@@ -24,37 +49,83 @@ where
     (client, rx)
 }
 
+/// Returns the codec chosen with the `Sec-WebSocket-Protocol` header
+/// in the headers of a handshake request or response, [`Json`] if there is none.
+///
+/// Clients offer codecs in the request with [`codec::offer`].
+pub fn negotiated_codec(headers: &http::HeaderMap, preferred: &Arc<dyn Codec>) -> Arc<dyn Codec> {
+    headers
+        .get(http::header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|protocol| protocol.to_str().ok())
+        .and_then(|protocol| codec::negotiate(protocol, preferred))
+        .unwrap_or_else(|| Arc::new(Json))
+}
+
+/// Accepts a WebSocket connection on `stream`, negotiating the codec
+/// with the `Sec-WebSocket-Protocol` header of the handshake.
+///
+/// Picks `preferred` if the peer offers it, otherwise [`Json`].
+/// Serve the connection with [`handle_tungstenite`]
+/// and a session built with the returned codec.
+pub async fn accept_with_codec<S>(
+    stream: S,
+    preferred: &Arc<dyn Codec>,
+) -> Result<(WebSocketStream<S>, Arc<dyn Codec>), tungstenite::Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut negotiated = None;
+    // The signature of the callback is given by tungstenite.
+    #[allow(clippy::result_large_err)]
+    let callback = |request: &Request, mut response: Response| {
+        negotiated = request
+            .headers()
+            .get(http::header::SEC_WEBSOCKET_PROTOCOL)
+            .and_then(|protocol| protocol.to_str().ok())
+            .and_then(|protocol| codec::negotiate(protocol, preferred));
+        if let Some(codec) = &negotiated {
+            response.headers_mut().insert(
+                http::header::SEC_WEBSOCKET_PROTOCOL,
+                http::HeaderValue::from_static(codec.subprotocol()),
+            );
+        }
+        Ok::<_, ErrorResponse>(response)
+    };
+    let stream = tokio_tungstenite::accept_hdr_async(stream, callback).await?;
+    Ok((stream, negotiated.unwrap_or_else(|| Arc::new(Json))))
+}
+
+/// Serves `session` over an established WebSocket with [`serve_frames`].
+///
+/// Messages are exchanged with the codec of the session's client,
+/// which has to match the codec negotiated during the handshake,
+/// see [`accept_with_codec`] and [`negotiated_codec`].
+/// Text frames are always JSON.
 pub async fn handle_tungstenite<R, S>(
-    mut stream: WebSocketStream<S>,
-    mut out_rx: OutReceiver,
+    stream: WebSocketStream<S>,
+    out_rx: OutReceiver,
     session: RpcSession<R>,
 ) -> anyhow::Result<()>
 where
     R: RpcServer,
     S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
 {
-    loop {
-        tokio::select! {
-            message = out_rx.next() => {
-                    let message = serde_json::to_string(&message)?;
-                    stream.send(Message::Text(message)).await?;
-            }
-            message = stream.next() => {
-                match message {
-                    Some(Ok(Message::Text(message))) => {
-                        session.handle_incoming(&message).await;
-                    },
-                    Some(Ok(Message::Binary(_))) => {
-                        return Err(anyhow::anyhow!("Binary messages are not supported."))
-                    }
-                    Some(Ok(_)) => {}
-                    Some(Err(err)) => {
-                        return Err(err.into())
-                    }
-                    None => break,
-                }
-            }
-        }
-    }
+    let codec = session.client().codec().clone();
+    let (sender, receiver) = stream.split();
+    let incoming = receiver.filter_map(|message| {
+        ready(match message {
+            Ok(Message::Text(message)) => Some(Ok(Frame::Text(message))),
+            Ok(Message::Binary(frame)) => Some(Ok(Frame::Binary(frame))),
+            Ok(_) => None,
+            Err(err) => Some(Err(err)),
+        })
+    });
+    let outgoing = sender.with(|frame| {
+        ready(Ok::<_, tungstenite::Error>(match frame {
+            Frame::Text(message) => Message::Text(message),
+            Frame::Binary(frame) => Message::Binary(frame),
+        }))
+    });
+    serve_frames(session, out_rx, &*codec, incoming, outgoing).await?;
     Ok(())
 }
@@ -1,20 +1,56 @@
 #![warn(clippy::wildcard_imports)]
 
 pub use async_trait::async_trait;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::value::RawValue;
 
-pub use yerpc_derive::rpc;
+pub use yerpc_derive::{rpc, RpcError};
 
+#[cfg(feature = "anyhow")]
+mod anyhow_error;
+pub mod codec;
+mod context;
+mod message;
+mod metrics;
+mod middleware;
 #[cfg(feature = "openrpc")]
 pub mod openrpc;
+mod progress;
+mod queue;
 mod requests;
+mod rpc_error;
+#[cfg(feature = "tracing")]
+mod spans;
+mod subscriptions;
+pub mod transport;
 pub mod typescript;
 mod version;
 
+#[cfg(feature = "anyhow")]
+pub use anyhow_error::{AnyhowConverter, AnyhowMessage};
+pub use context::{FromContext, Meta, MethodName, RequestContext, RequestId};
+pub use message::MessageError;
+pub use metrics::{
+    CallMetrics, CallOutcome, Direction, LatencyHistogram, MethodMetrics, Metrics, MetricsRecorder,
+    MetricsSnapshot, LATENCY_BOUNDS_MS,
+};
+pub use middleware::{LogMiddleware, Middleware, Next, RpcCall, TimingCallback, TimingMiddleware};
 #[cfg(feature = "openrpc")]
 pub use openrpc::JsonSchema;
-pub use requests::{OutReceiver, RpcClient, RpcSession, RpcSessionSink};
+pub use progress::{Progress, ProgressParams, PROGRESS};
+pub use queue::{OutReceiver, QueuePolicy, DEFAULT_QUEUE_CAPACITY};
+pub use requests::{
+    BatchResponse, ErrorHook, ResponseFuture, RpcBatch, RpcClient, RpcClientBuilder, RpcSession,
+    RpcSessionBuilder, RpcSessionSink, SessionError,
+};
+pub use rpc_error::{ErrorVariant, RpcError};
+pub use subscriptions::{
+    subscription_stream, SubscriptionEnd, SubscriptionId, SubscriptionItem, SubscriptionParams,
+    SubscriptionStream, SUBSCRIPTION, SUBSCRIPTION_END, UNSUBSCRIBE,
+};
+pub use transport::{serve, TransportError};
 pub use typescript::TypeDef;
+use typescript_type_def::type_expr;
 pub use version::Version;
 
 mod integrations;
@@ -31,6 +67,33 @@ pub trait RpcServer: Sync + Send + 'static {
     async fn handle_notification(&self, _method: String, _params: serde_json::Value) -> Result<()> {
         Ok(())
     }
+
+    /// Handles a notification with access to the request context.
+    ///
+    /// Defaults to [`RpcServer::handle_notification`].
+    async fn handle_notification_with_context(
+        &self,
+        method: String,
+        params: serde_json::Value,
+        _context: RequestContext,
+    ) -> Result<()> {
+        self.handle_notification(method, params).await
+    }
+
+    /// Handles a notification with params that are not decoded yet.
+    ///
+    /// Called by [`RpcSession`] for all notifications,
+    /// defaults to [`RpcServer::handle_notification_with_context`].
+    async fn handle_notification_raw(
+        &self,
+        method: String,
+        params: RawParams,
+        context: RequestContext,
+    ) -> Result<()> {
+        let params = raw_params_to_value(params)?;
+        self.handle_notification_with_context(method, params, context)
+            .await
+    }
     async fn handle_request(
         &self,
         _method: String,
@@ -41,6 +104,54 @@ pub trait RpcServer: Sync + Send + 'static {
             "Method not found".to_string(),
         ))
     }
+
+    /// Handles a request with access to the request context.
+    ///
+    /// Defaults to [`RpcServer::handle_request`].
+    async fn handle_request_with_context(
+        &self,
+        method: String,
+        params: serde_json::Value,
+        _context: RequestContext,
+    ) -> Result<serde_json::Value> {
+        self.handle_request(method, params).await
+    }
+
+    /// Handles a request with params that are not decoded yet.
+    ///
+    /// Called by [`RpcSession`] for all requests,
+    /// defaults to [`RpcServer::handle_request_with_context`].
+    /// The [`rpc`] macro implements it by decoding each argument
+    /// straight from the raw params and serializing the result
+    /// without building a [`serde_json::Value`].
+    async fn handle_request_raw(
+        &self,
+        method: String,
+        params: RawParams,
+        context: RequestContext,
+    ) -> Result<RawResult> {
+        let params = raw_params_to_value(params)?;
+        let result = self
+            .handle_request_with_context(method, params, context)
+            .await?;
+        to_raw_result(&result)
+    }
+
+    /// Returns true if `method` starts a subscription
+    /// and should be handled with [`RpcServer::handle_subscription`].
+    fn is_subscription(&self, _method: &str) -> bool {
+        false
+    }
+
+    /// Starts a subscription and returns the stream of its items.
+    async fn handle_subscription(
+        &self,
+        _method: String,
+        _params: RawParams,
+        _context: RequestContext,
+    ) -> Result<SubscriptionStream> {
+        Err(Error::method_not_found())
+    }
 }
 
 impl RpcServer for () {}
@@ -49,8 +160,22 @@ impl RpcServer for () {}
 #[derive(Serialize, Deserialize, Debug, TypeDef, Eq, Hash, PartialEq, Clone)]
 #[serde(untagged)]
 pub enum Id {
-    Number(u32),
+    Number(i64),
     String(String),
+    /// Explicit `null` identifier.
+    ///
+    /// Unlike a missing identifier, it does not make the request a notification.
+    Null,
+}
+
+/// Method name of the notification cancelling a request.
+pub const CANCEL_REQUEST: &str = "$/cancelRequest";
+
+/// Params of the `$/cancelRequest` notification.
+#[derive(Serialize, Deserialize, Debug, TypeDef)]
+pub struct CancelParams {
+    /// ID of the request to cancel.
+    pub id: Id,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -63,11 +188,44 @@ pub enum RpcResult<T: TypeDef> {
     Err(Error),
 }
 
-#[derive(Serialize, Deserialize, Debug, TypeDef)]
+/// JSON-RPC message.
+///
+/// A batch is an array of messages sent at once.
+/// Deserialization classifies messages by their fields,
+/// see [`Message::from_value`].
+#[derive(Serialize, Debug)]
 #[serde(untagged)]
 pub enum Message {
     Request(Request),
     Response(Response),
+    Batch(Vec<Message>),
+}
+
+// The TypeDef macro cannot handle the recursive `Batch` variant,
+// so here we implement TypeDef manually.
+impl TypeDef for Message {
+    const INFO: type_expr::TypeInfo = type_expr::TypeInfo::Defined(type_expr::DefinedTypeInfo {
+        def: type_expr::TypeDefinition {
+            docs: Some(type_expr::Docs(
+                "JSON-RPC message.\n\nA batch is an array of messages sent at once.",
+            )),
+            path: &[],
+            name: type_expr::Ident("Message"),
+            generic_vars: &[],
+            def: type_expr::TypeExpr::Union(type_expr::TypeUnion {
+                docs: None,
+                members: &[
+                    type_expr::TypeExpr::Ref(&Request::INFO),
+                    type_expr::TypeExpr::Ref(&Response::INFO),
+                    type_expr::TypeExpr::Array(type_expr::TypeArray {
+                        docs: None,
+                        item: &type_expr::TypeExpr::ident(type_expr::Ident("Message")),
+                    }),
+                ],
+            }),
+        },
+        generic_args: &[],
+    });
 }
 
 #[derive(Serialize, Deserialize, Debug, TypeDef)]
@@ -86,6 +244,97 @@ impl Params {
     }
 }
 
+/// Params of an incoming call as raw JSON, `None` if there are none.
+///
+/// Params stay undecoded until the handler deserializes each argument
+/// straight into its type, without building a [`serde_json::Value`] first.
+pub type RawParams = Option<Box<RawValue>>;
+
+/// Converts params into raw JSON, `null` into `None`.
+pub fn value_to_raw_params(params: serde_json::Value) -> Result<RawParams> {
+    match params {
+        serde_json::Value::Null => Ok(None),
+        params => Ok(Some(serde_json::value::to_raw_value(&params)?)),
+    }
+}
+
+/// Result of a call as raw JSON, written into the response frame as is.
+pub type RawResult = Box<RawValue>;
+
+/// Serializes the result of a call.
+///
+/// Failures are reported as [`Error::INTERNAL_ERROR`].
+pub fn to_raw_result<T: Serialize + ?Sized>(result: &T) -> Result<RawResult> {
+    serde_json::value::to_raw_value(result).map_err(|err| Error::internal(err.to_string()))
+}
+
+/// Decodes raw params into a JSON value, `None` into `null`.
+pub fn raw_params_to_value(params: RawParams) -> Result<serde_json::Value> {
+    match params {
+        None => Ok(serde_json::Value::Null),
+        Some(params) => Ok(serde_json::from_str(params.get())?),
+    }
+}
+
+/// Deserializes a method parameter.
+///
+/// Failures are reported as [`Error::INVALID_PARAMS`] with the parameter name,
+/// its position for positional parameters and the path to the invalid field
+/// in the error data.
+pub fn deserialize_param<T: DeserializeOwned>(
+    value: serde_json::Value,
+    name: &str,
+    position: Option<usize>,
+) -> Result<T> {
+    serde_path_to_error::deserialize(value).map_err(|err| invalid_param(err, name, position))
+}
+
+/// Deserializes a method parameter from raw JSON, `None` as `null`.
+///
+/// Used by the code generated with the [`rpc`] macro.
+/// Reports failures like [`deserialize_param`].
+pub fn deserialize_raw_param<T: DeserializeOwned>(
+    raw: Option<&RawValue>,
+    name: &str,
+    position: Option<usize>,
+) -> Result<T> {
+    let json = raw.map_or("null", RawValue::get);
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    serde_path_to_error::deserialize(&mut deserializer)
+        .map_err(|err| invalid_param(err, name, position))
+}
+
+fn invalid_param(
+    err: serde_path_to_error::Error<serde_json::Error>,
+    name: &str,
+    position: Option<usize>,
+) -> Error {
+    let mut data = serde_json::Map::new();
+    data.insert("param".to_string(), name.into());
+    if let Some(position) = position {
+        data.insert("position".to_string(), position.into());
+    }
+    let path = err.path().to_string();
+    // The path of the parameter itself is ".".
+    if path != "." {
+        data.insert("path".to_string(), path.into());
+    }
+    let inner = err.inner();
+    let mut message = inner.to_string();
+    // Positions within the raw parameter are meaningless to the caller.
+    if inner.line() != 0 {
+        let suffix = format!(" at line {} column {}", inner.line(), inner.column());
+        if message.ends_with(&suffix) {
+            message.truncate(message.len() - suffix.len());
+        }
+    }
+    Error::with_data(
+        Error::INVALID_PARAMS,
+        format!("Invalid parameter `{name}`: {message}"),
+        Some(data.into()),
+    )
+}
+
 impl From<Params> for serde_json::Value {
     fn from(params: Params) -> Self {
         params.into_value()
@@ -118,10 +367,27 @@ pub struct Request {
     pub params: Option<Params>,
 
     /// Request identifier.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    ///
+    /// Missing for notifications.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_present"
+    )]
     pub id: Option<Id>,
 }
 
+/// Deserializes a field that is present as `Some`, even if it is `null`.
+///
+/// Use together with `#[serde(default)]` so that a missing field is `None`.
+fn deserialize_present<'de, D, T>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
 /// Response object.
 #[derive(Serialize, Deserialize, Debug, TypeDef)]
 pub struct Response {
@@ -190,14 +456,14 @@ impl Error {
     pub const BAD_REQUEST: i32 = -32000;
     pub const BAD_RESPONSE: i32 = -32001;
     pub const REMOTE_DISCONNECTED: i32 = -32002;
+    pub const TIMEOUT: i32 = -32003;
+    pub const SERVER_BUSY: i32 = -32004;
+    pub const QUEUE_FULL: i32 = -32005;
+    pub const REQUEST_CANCELLED: i32 = -32800;
 
     /// Creates a new error object.
     pub fn new(code: i32, message: String) -> Self {
-        Self {
-            code,
-            message,
-            data: None,
-        }
+        Self::with_data(code, message, None)
     }
 
     /// Creates a new error object with additional information.
@@ -209,6 +475,11 @@ impl Error {
         }
     }
 
+    /// Creates a new error object indicating that the request object is not valid.
+    pub fn invalid_request() -> Self {
+        Self::new(Error::INVALID_REQUEST, "Invalid request".to_string())
+    }
+
     /// Creates a new error object indicating invalid method parameters.
     pub fn invalid_params() -> Self {
         Self::new(
@@ -229,6 +500,12 @@ impl Error {
         )
     }
 
+    /// Creates a new error object indicating an internal error,
+    /// such as a result that cannot be serialized.
+    pub fn internal(message: String) -> Self {
+        Self::new(Error::INTERNAL_ERROR, message)
+    }
+
     pub fn bad_response() -> Self {
         Self::new(
             Error::BAD_RESPONSE,
@@ -248,41 +525,38 @@ impl Error {
         )
     }
 
+    pub fn timeout() -> Self {
+        Self::new(Error::TIMEOUT, "Request timed out".to_string())
+    }
+
+    pub fn server_busy() -> Self {
+        Self::new(Error::SERVER_BUSY, "Server busy".to_string())
+    }
+
+    /// Creates a new error object indicating that the outgoing queue is full.
+    pub fn queue_full() -> Self {
+        Self::new(Error::QUEUE_FULL, "Outgoing queue full".to_string())
+    }
+
+    pub fn request_cancelled() -> Self {
+        Self::new(Error::REQUEST_CANCELLED, "Request cancelled".to_string())
+    }
+
     pub fn is_disconnnected(&self) -> bool {
         self.code == Error::REMOTE_DISCONNECTED
     }
-}
 
-impl From<serde_json::Error> for Error {
-    fn from(error: serde_json::Error) -> Self {
-        Self {
-            code: Error::PARSE_ERROR,
-            message: format!("{error}"),
-            data: None,
-        }
+    pub fn is_timeout(&self) -> bool {
+        self.code == Error::TIMEOUT
     }
-}
 
-#[cfg(feature = "anyhow")]
-#[cfg(feature = "anyhow_expose")]
-impl From<anyhow::Error> for Error {
-    fn from(error: anyhow::Error) -> Self {
-        Self {
-            code: -1,
-            message: format!("{:?}", error),
-            data: None,
-        }
+    pub fn is_cancelled(&self) -> bool {
+        self.code == Error::REQUEST_CANCELLED
     }
 }
 
-#[cfg(feature = "anyhow")]
-#[cfg(not(feature = "anyhow_expose"))]
-impl From<anyhow::Error> for Error {
-    fn from(_error: anyhow::Error) -> Self {
-        Self {
-            code: Error::INTERNAL_ERROR,
-            message: "Internal server error".to_string(),
-            data: None,
-        }
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Self::new(Error::PARSE_ERROR, format!("{error}"))
     }
 }
@@ -0,0 +1,311 @@
+//! Parsing of incoming JSON-RPC messages and encoding of outgoing responses.
+//!
+//! Messages are classified by their fields instead of trying each variant in turn,
+//! so that errors can tell invalid JSON apart from JSON that is not a valid
+//! request or response, and refer to the request ID whenever it is known.
+use serde::de;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::value::RawValue;
+use serde_json::{Map, Value};
+use std::str::FromStr;
+
+use crate::{Error, Id, Message, Params, RawParams, RawResult, Request, Response, Version};
+
+/// Reason why a JSON value is not a valid message.
+#[derive(Debug)]
+pub struct MessageError {
+    /// Identifier of the invalid request, if it could be recovered.
+    pub id: Option<Id>,
+
+    /// Error to report to the sender.
+    pub error: Error,
+}
+
+impl MessageError {
+    fn invalid_request(id: Option<Id>, message: &str) -> Self {
+        Self {
+            id,
+            error: Error::new(Error::INVALID_REQUEST, message.to_string()),
+        }
+    }
+}
+
+impl FromStr for Message {
+    type Err = MessageError;
+
+    /// Parses a message from a JSON string.
+    ///
+    /// Invalid JSON results in [`Error::PARSE_ERROR`],
+    /// valid JSON that is not a message in [`Error::INVALID_REQUEST`].
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let value: Value = serde_json::from_str(input).map_err(|err| MessageError {
+            id: None,
+            error: Error::new(Error::PARSE_ERROR, err.to_string()),
+        })?;
+        Self::from_value(value)
+    }
+}
+
+impl Message {
+    /// Converts a JSON value into a message.
+    ///
+    /// Arrays are parsed as batches and fail if any of their elements is invalid.
+    pub fn from_value(value: Value) -> Result<Self, MessageError> {
+        match value {
+            Value::Object(object) => Self::from_object(object),
+            Value::Array(values) => values
+                .into_iter()
+                .map(Self::from_value)
+                .collect::<Result<_, _>>()
+                .map(Message::Batch),
+            _ => Err(MessageError::invalid_request(
+                None,
+                "Message must be an object or array",
+            )),
+        }
+    }
+
+    fn from_object(mut object: Map<String, Value>) -> Result<Self, MessageError> {
+        let fields = Fields {
+            id: object.remove("id"),
+            jsonrpc: object.remove("jsonrpc"),
+            method: object.remove("method"),
+            params: object.remove("params").filter(|params| !params.is_null()),
+            result: object.remove("result"),
+            error: object.remove("error"),
+        };
+        match fields.classify(|params| Params::try_from(params).ok())? {
+            Classified::Request(call) => Ok(Message::Request(Request {
+                jsonrpc: call.jsonrpc,
+                method: call.method,
+                params: call.params,
+                id: call.id,
+            })),
+            Classified::Response(response) => Ok(Message::Response(response)),
+        }
+    }
+}
+
+/// Incoming request or notification with params that are not decoded yet.
+pub(crate) struct RawRequest {
+    pub(crate) method: String,
+    pub(crate) params: RawParams,
+    pub(crate) id: Option<Id>,
+}
+
+/// Incoming message that is not a batch.
+pub(crate) enum Incoming {
+    Request(RawRequest),
+    /// Response with the size of its JSON in bytes.
+    Response(Response, usize),
+}
+
+/// Incoming frame, a single message or a batch.
+pub(crate) enum Frame {
+    Single(Result<Incoming, MessageError>),
+    Batch(Vec<Result<Incoming, MessageError>>),
+}
+
+impl Frame {
+    /// Parses a frame, keeping request params as raw JSON.
+    ///
+    /// Fails with [`Error::PARSE_ERROR`] for invalid JSON,
+    /// messages that are not valid are returned as errors in the frame.
+    pub(crate) fn parse(input: &str) -> Result<Self, MessageError> {
+        let parse_error = |err: serde_json::Error| MessageError {
+            id: None,
+            error: Error::new(Error::PARSE_ERROR, err.to_string()),
+        };
+        let raw: &RawValue = serde_json::from_str(input).map_err(parse_error)?;
+        if raw.get().starts_with('[') {
+            let messages: Vec<&RawValue> = serde_json::from_str(raw.get()).map_err(parse_error)?;
+            let messages = messages.into_iter().map(Incoming::parse).collect();
+            Ok(Frame::Batch(messages))
+        } else {
+            Ok(Frame::Single(Incoming::parse(raw)))
+        }
+    }
+}
+
+/// Outgoing response with a result that is already serialized.
+///
+/// Serializes like [`Response`], embedding the raw result as is.
+#[derive(Serialize, Debug)]
+pub(crate) struct RawResponse {
+    jsonrpc: Version,
+    pub(crate) id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) result: Option<RawResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<Error>,
+}
+
+impl RawResponse {
+    pub(crate) fn success(id: Id, result: RawResult) -> Self {
+        Self {
+            jsonrpc: Version::V2,
+            id: Some(id),
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    pub(crate) fn error(id: Option<Id>, error: Error) -> Self {
+        Self {
+            jsonrpc: Version::V2,
+            id,
+            result: None,
+            error: Some(error),
+        }
+    }
+}
+
+/// Serializes an outgoing message or batch into the text sent to the remote side.
+pub(crate) fn encode(message: &impl Serialize) -> Result<String, Error> {
+    serde_json::to_string(message).map_err(|err| Error::internal(err.to_string()))
+}
+
+impl Incoming {
+    fn parse(raw: &RawValue) -> Result<Self, MessageError> {
+        if raw.get().starts_with('[') {
+            // Batches cannot be nested.
+            return Err(MessageError {
+                id: None,
+                error: Error::invalid_request(),
+            });
+        }
+        if !raw.get().starts_with('{') {
+            return Err(MessageError::invalid_request(
+                None,
+                "Message must be an object or array",
+            ));
+        }
+        let fields: Fields<&RawValue> = serde_json::from_str(raw.get())
+            .map_err(|_| MessageError::invalid_request(None, "Invalid message object"))?;
+        let is_structured = |params: &RawValue| {
+            let json = params.get();
+            json.starts_with('[') || json.starts_with('{')
+        };
+        let classified =
+            fields.classify(|params| is_structured(params).then(|| params.to_owned()))?;
+        match classified {
+            Classified::Request(call) => Ok(Incoming::Request(RawRequest {
+                method: call.method,
+                params: call.params,
+                id: call.id,
+            })),
+            Classified::Response(response) => Ok(Incoming::Response(response, raw.get().len())),
+        }
+    }
+}
+
+/// Fields of a message object, `P` being the type of the params.
+///
+/// Fields are `Some` if present, even if `null`, except for the params.
+#[derive(Deserialize)]
+struct Fields<P> {
+    #[serde(default, deserialize_with = "present")]
+    id: Option<Value>,
+    #[serde(default, deserialize_with = "present")]
+    jsonrpc: Option<Value>,
+    #[serde(default, deserialize_with = "present")]
+    method: Option<Value>,
+    params: Option<P>,
+    #[serde(default, deserialize_with = "present")]
+    result: Option<Value>,
+    #[serde(default, deserialize_with = "present")]
+    error: Option<Value>,
+}
+
+/// Deserializes a field that is present, including `null`.
+fn present<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    Value::deserialize(deserializer).map(Some)
+}
+
+/// Message classified by its fields.
+enum Classified<P> {
+    Request(Call<P>),
+    Response(Response),
+}
+
+/// Request or notification with params of type `P`.
+struct Call<P> {
+    jsonrpc: Option<Version>,
+    method: String,
+    params: Option<P>,
+    id: Option<Id>,
+}
+
+impl<P> Fields<P> {
+    /// Classifies the message, converting the params with `convert`,
+    /// which returns `None` if they are neither an object nor an array.
+    fn classify<T>(
+        self,
+        convert: impl FnOnce(P) -> Option<T>,
+    ) -> Result<Classified<T>, MessageError> {
+        // Recover the ID first so that all further errors can refer to it.
+        let id = match self.id {
+            None => None,
+            Some(id) => Some(
+                serde_json::from_value::<Id>(id)
+                    .map_err(|_| MessageError::invalid_request(None, "Invalid id"))?,
+            ),
+        };
+        let invalid = |message: &str| MessageError::invalid_request(id.clone(), message);
+
+        // JSON-RPC 1.0 messages have no jsonrpc field.
+        let jsonrpc = match self.jsonrpc {
+            None => None,
+            Some(Value::String(version)) if version == "2.0" => Some(Version::V2),
+            Some(_) => return Err(invalid("Unsupported JSON-RPC version, expected \"2.0\"")),
+        };
+
+        if let Some(method) = self.method {
+            let Value::String(method) = method else {
+                return Err(invalid("Method must be a string"));
+            };
+            let params = match self.params {
+                None => None,
+                Some(params) => Some(
+                    convert(params).ok_or_else(|| invalid("Params must be an object or array"))?,
+                ),
+            };
+            return Ok(Classified::Request(Call {
+                jsonrpc,
+                method,
+                params,
+                id,
+            }));
+        }
+
+        if self.result.is_some() || self.error.is_some() {
+            let error = match self.error {
+                None | Some(Value::Null) => None,
+                Some(error) => Some(
+                    serde_json::from_value(error).map_err(|_| invalid("Invalid error object"))?,
+                ),
+            };
+            // A present error takes precedence over the result.
+            let result = self.result.filter(|_| error.is_none());
+            return Ok(Classified::Response(Response {
+                jsonrpc: Version::V2,
+                // Responses to requests with unknown IDs have a null ID.
+                id: id.filter(|id| *id != Id::Null),
+                result,
+                error,
+            }));
+        }
+
+        Err(invalid("Message is neither a request nor a response"))
+    }
+}
+
+impl<'de> Deserialize<'de> for Message {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = Value::deserialize(deserializer)?;
+        Message::from_value(value).map_err(|err| de::Error::custom(err.error.message))
+    }
+}
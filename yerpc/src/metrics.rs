@@ -0,0 +1,299 @@
+//! Per-method metrics of incoming and outgoing calls.
+//!
+//! A [`MetricsRecorder`] attached with [`RpcSession::with_metrics`](crate::RpcSession::with_metrics)
+//! or [`RpcClient::with_metrics`](crate::RpcClient::with_metrics) is notified
+//! when calls start and finish. Implement it to forward the measurements
+//! to a metrics facade, or use the in-memory [`Metrics`] recorder
+//! and expose its [`MetricsSnapshot`], e.g. through an RPC method.
+//!
+//! Payload sizes are taken from the JSON that is sent and received anyway,
+//! nothing is serialized again for measuring.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{Error, TypeDef};
+
+/// Direction of a call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Call received from the remote side and handled by the session.
+    Incoming,
+
+    /// Request sent to the remote side by the client.
+    Outgoing,
+}
+
+/// How a call ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallOutcome {
+    /// The call succeeded.
+    Success,
+
+    /// The call failed with the given error code.
+    Error(i32),
+
+    /// The call was dropped before it finished,
+    /// e.g. because the future of a request was dropped.
+    Dropped,
+}
+
+/// Measurements of a finished call.
+#[derive(Debug, Clone)]
+pub struct CallMetrics<'a> {
+    /// Direction of the call.
+    pub direction: Direction,
+
+    /// Method name.
+    pub method: &'a str,
+
+    /// Time from the start of the call until its result was available.
+    pub duration: Duration,
+
+    /// How the call ended.
+    pub outcome: CallOutcome,
+
+    /// Size of the serialized params in bytes.
+    ///
+    /// For outgoing requests the size of the whole request message.
+    pub params_bytes: usize,
+
+    /// Size of the serialized result in bytes, 0 for failed calls and notifications.
+    ///
+    /// For outgoing requests the size of the whole response message.
+    pub result_bytes: usize,
+}
+
+/// Receiver of call measurements.
+pub trait MetricsRecorder: Send + Sync + 'static {
+    /// Called when a call starts.
+    ///
+    /// Every call is followed by exactly one [`MetricsRecorder::call_finished`].
+    fn call_started(&self, direction: Direction, method: &str);
+
+    /// Called when a call finishes, fails or is dropped.
+    fn call_finished(&self, call: &CallMetrics<'_>);
+}
+
+/// Upper bounds of the latency histogram buckets in milliseconds.
+pub const LATENCY_BOUNDS_MS: &[u64] = &[1, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+/// In-memory recorder aggregating measurements per method.
+///
+/// Clones share the same statistics.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    snapshot: Arc<Mutex<MetricsSnapshot>>,
+}
+
+impl Metrics {
+    /// Creates a recorder without any measurements.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the current statistics.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        self.snapshot.lock().unwrap().clone()
+    }
+
+    /// Clears all statistics except the calls in flight.
+    pub fn reset(&self) {
+        let snapshot = &mut *self.snapshot.lock().unwrap();
+        for methods in [&mut snapshot.incoming, &mut snapshot.outgoing] {
+            methods.retain(|_, method| method.in_flight > 0);
+            for method in methods.values_mut() {
+                *method = MethodMetrics {
+                    in_flight: method.in_flight,
+                    ..Default::default()
+                };
+            }
+        }
+    }
+
+    fn update(&self, direction: Direction, method: &str, update: impl FnOnce(&mut MethodMetrics)) {
+        let mut snapshot = self.snapshot.lock().unwrap();
+        let methods = match direction {
+            Direction::Incoming => &mut snapshot.incoming,
+            Direction::Outgoing => &mut snapshot.outgoing,
+        };
+        match methods.get_mut(method) {
+            Some(metrics) => update(metrics),
+            None => update(methods.entry(method.to_string()).or_default()),
+        }
+    }
+}
+
+impl MetricsRecorder for Metrics {
+    fn call_started(&self, direction: Direction, method: &str) {
+        self.update(direction, method, |metrics| metrics.in_flight += 1);
+    }
+
+    fn call_finished(&self, call: &CallMetrics<'_>) {
+        self.update(call.direction, call.method, |metrics| {
+            metrics.in_flight = metrics.in_flight.saturating_sub(1);
+            match call.outcome {
+                CallOutcome::Success => {}
+                CallOutcome::Error(code) => *metrics.errors.entry(code).or_default() += 1,
+                CallOutcome::Dropped => {
+                    metrics.dropped += 1;
+                    return;
+                }
+            }
+            metrics.calls += 1;
+            metrics.latency.observe(call.duration);
+            metrics.params_bytes += call.params_bytes as u64;
+            metrics.result_bytes += call.result_bytes as u64;
+        });
+    }
+}
+
+/// Statistics of all methods, by method name.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, TypeDef)]
+pub struct MetricsSnapshot {
+    /// Calls handled by the session.
+    pub incoming: BTreeMap<String, MethodMetrics>,
+
+    /// Requests sent by the client.
+    pub outgoing: BTreeMap<String, MethodMetrics>,
+}
+
+/// Statistics of a single method.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, TypeDef)]
+#[serde(rename_all = "camelCase")]
+pub struct MethodMetrics {
+    /// Number of finished calls, including failed ones.
+    pub calls: u64,
+
+    /// Number of calls dropped before they finished.
+    ///
+    /// They are not included in the other statistics.
+    pub dropped: u64,
+
+    /// Number of failed calls by error code.
+    pub errors: BTreeMap<i32, u64>,
+
+    /// Number of calls currently in flight.
+    pub in_flight: u64,
+
+    /// Latency of the finished calls.
+    pub latency: LatencyHistogram,
+
+    /// Total size of the params in bytes.
+    pub params_bytes: u64,
+
+    /// Total size of the results in bytes.
+    pub result_bytes: u64,
+}
+
+/// Histogram of call latencies.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, TypeDef)]
+#[serde(rename_all = "camelCase")]
+pub struct LatencyHistogram {
+    /// Upper bounds of the buckets in milliseconds, see [`LATENCY_BOUNDS_MS`].
+    pub bounds_ms: Vec<u64>,
+
+    /// Number of calls per bucket.
+    ///
+    /// Has one more entry than `bounds_ms` counting the calls above the last bound.
+    pub counts: Vec<u64>,
+
+    /// Sum of all latencies in milliseconds.
+    pub sum_ms: f64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            bounds_ms: LATENCY_BOUNDS_MS.to_vec(),
+            counts: vec![0; LATENCY_BOUNDS_MS.len() + 1],
+            sum_ms: 0.0,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    /// Adds a call taking `duration` to the histogram.
+    pub fn observe(&mut self, duration: Duration) {
+        let ms = duration.as_secs_f64() * 1000.0;
+        let bucket = self
+            .bounds_ms
+            .iter()
+            .position(|&bound| ms <= bound as f64)
+            .unwrap_or(self.bounds_ms.len());
+        if let Some(count) = self.counts.get_mut(bucket) {
+            *count += 1;
+        }
+        self.sum_ms += ms;
+    }
+
+    /// Returns the total number of calls.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+}
+
+/// Measures a call from its start until it is finished or dropped.
+pub(crate) struct CallTimer {
+    recorder: Arc<dyn MetricsRecorder>,
+    direction: Direction,
+    method: String,
+    params_bytes: usize,
+    start: Instant,
+    finished: bool,
+}
+
+impl CallTimer {
+    /// Starts measuring a call with params of `params_bytes` bytes.
+    pub(crate) fn start(
+        recorder: &Arc<dyn MetricsRecorder>,
+        direction: Direction,
+        method: &str,
+        params_bytes: usize,
+    ) -> Self {
+        recorder.call_started(direction, method);
+        Self {
+            recorder: recorder.clone(),
+            direction,
+            method: method.to_string(),
+            params_bytes,
+            start: Instant::now(),
+            finished: false,
+        }
+    }
+
+    /// Sets the size of the params once they are serialized.
+    pub(crate) fn set_params_bytes(&mut self, params_bytes: usize) {
+        self.params_bytes = params_bytes;
+    }
+
+    /// Reports the outcome of the call with the size of its serialized result,
+    /// 0 for failed calls and notifications.
+    pub(crate) fn finish(mut self, error: Option<&Error>, result_bytes: usize) {
+        match error {
+            Some(error) => self.report(CallOutcome::Error(error.code), 0),
+            None => self.report(CallOutcome::Success, result_bytes),
+        }
+    }
+
+    fn report(&mut self, outcome: CallOutcome, result_bytes: usize) {
+        self.finished = true;
+        self.recorder.call_finished(&CallMetrics {
+            direction: self.direction,
+            method: &self.method,
+            duration: self.start.elapsed(),
+            outcome,
+            params_bytes: self.params_bytes,
+            result_bytes,
+        });
+    }
+}
+
+impl Drop for CallTimer {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.report(CallOutcome::Dropped, 0);
+        }
+    }
+}
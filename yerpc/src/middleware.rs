@@ -0,0 +1,154 @@
+//! Middlewares wrapping the dispatch of incoming requests and notifications.
+//!
+//! Middlewares are attached to a session with [`RpcSession::with_middleware`]
+//! and run in the order they were added, each one wrapping all that follow.
+//! Built-in methods such as `$/cancelRequest` bypass them.
+//!
+//! [`RpcSession::with_middleware`]: crate::RpcSession::with_middleware
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::{Id, RawParams, RawResult, Result};
+
+/// Incoming request or notification as seen by middlewares.
+#[derive(Debug, Clone)]
+pub struct RpcCall {
+    /// Request ID, `None` for notifications.
+    pub id: Option<Id>,
+
+    /// Method name.
+    pub method: String,
+
+    /// Request params as raw JSON, `None` if there are none.
+    ///
+    /// Decode them with [`raw_params_to_value`](crate::raw_params_to_value)
+    /// or [`serde_json::from_str`].
+    pub params: RawParams,
+}
+
+impl RpcCall {
+    /// Returns true if the call is a notification.
+    pub fn is_notification(&self) -> bool {
+        self.id.is_none()
+    }
+}
+
+/// Interceptor wrapping the handling of incoming calls.
+///
+/// A middleware can inspect and modify the call before passing it on with
+/// [`Next::run`], inspect and modify the result afterwards,
+/// or return an error without calling the handler at all.
+/// Results are raw JSON, decode them with [`serde_json::from_str`]
+/// and encode new ones with [`to_raw_result`](crate::to_raw_result).
+/// Results of notifications are `null` and are never sent.
+#[async_trait]
+pub trait Middleware: Send + Sync + 'static {
+    async fn handle(&self, call: RpcCall, next: Next<'_>) -> Result<RawResult>;
+}
+
+/// Handler passed to the middleware after all middlewares.
+pub(crate) type Endpoint<'a> =
+    dyn Fn(RpcCall) -> BoxFuture<'a, Result<RawResult>> + Send + Sync + 'a;
+
+/// Remaining middlewares and the handler of a call.
+pub struct Next<'a> {
+    middlewares: &'a [Arc<dyn Middleware>],
+    endpoint: &'a Endpoint<'a>,
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(middlewares: &'a [Arc<dyn Middleware>], endpoint: &'a Endpoint<'a>) -> Self {
+        Self {
+            middlewares,
+            endpoint,
+        }
+    }
+
+    /// Passes the call to the next middleware or, after the last one, to the handler.
+    pub fn run(self, call: RpcCall) -> BoxFuture<'a, Result<RawResult>> {
+        match self.middlewares.split_first() {
+            Some((middleware, middlewares)) => {
+                let next = Next::new(middlewares, self.endpoint);
+                Box::pin(async move { middleware.handle(call, next).await })
+            }
+            None => (self.endpoint)(call),
+        }
+    }
+}
+
+/// Middleware logging incoming calls and their outcome.
+#[derive(Debug, Clone)]
+pub struct LogMiddleware {
+    level: log::Level,
+}
+
+impl LogMiddleware {
+    /// Creates a middleware logging at `level`.
+    pub fn new(level: log::Level) -> Self {
+        Self { level }
+    }
+}
+
+impl Default for LogMiddleware {
+    fn default() -> Self {
+        Self::new(log::Level::Debug)
+    }
+}
+
+#[async_trait]
+impl Middleware for LogMiddleware {
+    async fn handle(&self, call: RpcCall, next: Next<'_>) -> Result<RawResult> {
+        let method = call.method.clone();
+        let id = call.id.clone();
+        log::log!(self.level, "JSON-RPC call {method} (id {id:?})");
+        let res = next.run(call).await;
+        match &res {
+            Ok(_) => log::log!(self.level, "JSON-RPC call {method} (id {id:?}) succeeded"),
+            Err(err) => log::log!(
+                self.level,
+                "JSON-RPC call {method} (id {id:?}) failed with {}: {}",
+                err.code,
+                err.message
+            ),
+        }
+        res
+    }
+}
+
+/// Callback receiving the method name and handling time of a call.
+pub type TimingCallback = Arc<dyn Fn(&str, Duration) + Send + Sync>;
+
+/// Middleware measuring how long calls take to handle.
+#[derive(Clone)]
+pub struct TimingMiddleware {
+    callback: TimingCallback,
+}
+
+impl TimingMiddleware {
+    /// Creates a middleware passing the method name and the handling time
+    /// of each call to `callback`.
+    pub fn new(callback: impl Fn(&str, Duration) + Send + Sync + 'static) -> Self {
+        Self {
+            callback: Arc::new(callback),
+        }
+    }
+}
+
+impl std::fmt::Debug for TimingMiddleware {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TimingMiddleware").finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl Middleware for TimingMiddleware {
+    async fn handle(&self, call: RpcCall, next: Next<'_>) -> Result<RawResult> {
+        let method = call.method.clone();
+        let start = Instant::now();
+        let res = next.run(call).await;
+        (self.callback)(&method, start.elapsed());
+        res
+    }
+}
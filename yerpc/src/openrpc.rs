@@ -39,6 +39,42 @@ pub struct Method {
 
     /// Whether request params are an array or an object.
     pub param_structure: ParamStructure,
+
+    /// Whether the method starts a subscription.
+    ///
+    /// The result is the schema of the subscription items,
+    /// the request itself responds with the subscription ID.
+    #[serde(rename = "x-subscription", skip_serializing_if = "std::ops::Not::not")]
+    pub subscription: bool,
+
+    /// Whether the method reports progress with `$/progress` notifications.
+    #[serde(rename = "x-progress", skip_serializing_if = "std::ops::Not::not")]
+    pub progress: bool,
+
+    /// Errors the method can fail with.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<ErrorObject>,
+}
+
+/// [Error Object](https://spec.open-rpc.org/#error-object)
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorObject {
+    /// Error code.
+    pub code: i32,
+
+    /// Short error description.
+    pub message: String,
+}
+
+/// Lists the errors of a typed error enum.
+pub fn errors<E: crate::RpcError>() -> Vec<ErrorObject> {
+    E::VARIANTS
+        .iter()
+        .map(|variant| ErrorObject {
+            code: variant.code,
+            message: variant.message.to_string(),
+        })
+        .collect()
 }
 
 #[derive(Debug, Clone, Serialize)]
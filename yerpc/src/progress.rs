@@ -0,0 +1,87 @@
+//! Progress reported by long-running requests.
+//!
+//! A method taking a `#[rpc(context)]` [`Progress`] argument receives a handle
+//! that sends `$/progress` notifications carrying the request ID,
+//! so the client can correlate them with the pending request.
+//! The argument is not part of the request params.
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::marker::PhantomData;
+
+use crate::{Error, Id, RpcClient, TypeDef};
+
+/// Method name of the notification reporting progress of a request.
+pub const PROGRESS: &str = "$/progress";
+
+/// Params of the `$/progress` notification.
+#[derive(Serialize, Deserialize, Debug, TypeDef)]
+pub struct ProgressParams {
+    /// ID of the request making progress.
+    pub id: Id,
+
+    /// Reported progress.
+    pub value: serde_json::Value,
+}
+
+/// Handle reporting progress of the request being processed.
+///
+/// `T` is the type of the reported progress values.
+/// Handles not bound to a request, such as the ones
+/// passed to notifications, discard all reports.
+pub struct Progress<T = serde_json::Value> {
+    target: Option<(RpcClient, Id)>,
+    _value: PhantomData<fn(T)>,
+}
+
+impl<T> Progress<T> {
+    /// Creates a handle reporting progress of the request `id` through `client`.
+    pub fn new(client: RpcClient, id: Id) -> Self {
+        Self {
+            target: Some((client, id)),
+            _value: PhantomData,
+        }
+    }
+
+    /// Returns the ID of the request this handle reports progress of.
+    pub fn id(&self) -> Option<&Id> {
+        self.target.as_ref().map(|(_, id)| id)
+    }
+}
+
+impl<T: Serialize> Progress<T> {
+    /// Sends a `$/progress` notification with `value` to the client.
+    pub async fn report(&self, value: T) -> Result<(), Error> {
+        let Some((client, id)) = &self.target else {
+            return Ok(());
+        };
+        let params = ProgressParams {
+            id: id.clone(),
+            value: serde_json::to_value(value)?,
+        };
+        client.send_notification(PROGRESS, Some(params)).await
+    }
+}
+
+impl<T> Default for Progress<T> {
+    fn default() -> Self {
+        Self {
+            target: None,
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<T> Clone for Progress<T> {
+    fn clone(&self) -> Self {
+        Self {
+            target: self.target.clone(),
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<T> fmt::Debug for Progress<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Progress").field("id", &self.id()).finish()
+    }
+}
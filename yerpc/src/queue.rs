@@ -0,0 +1,498 @@
+//! Queue of outgoing messages between the client and the transport.
+//!
+//! Messages are queued already serialized, so the transport sends them as they are.
+//! Responses take priority: they have their own queue, drained first,
+//! so answering the remote side never waits for a backlog of notifications.
+//! Progress notifications are queued with the responses,
+//! so they still arrive before the response of their request.
+//! Both queues have the same fixed capacity,
+//! what happens when one is full is set by a [`QueuePolicy`].
+//! Tasks forwarding subscription items are handed over to the future returned by
+//! [`OutReceiver::forward_subscriptions`], which runs them apart from reading,
+//! so they need no executor and stop when the queue is closed.
+use async_channel::{RecvError, TryRecvError};
+use event_listener::{Event, EventListener};
+use futures::future::{poll_fn, BoxFuture};
+use futures::stream::FuturesUnordered;
+use futures::{Stream, StreamExt};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use crate::{Error, Message, PROGRESS};
+
+/// Default capacity of the outgoing queue.
+pub const DEFAULT_QUEUE_CAPACITY: usize = 10;
+
+/// Behavior of the outgoing queue when it is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueuePolicy {
+    /// Wait until the transport takes a message from the queue.
+    #[default]
+    Block,
+
+    /// Drop the oldest queued notification to make room,
+    /// wait if there is none.
+    ///
+    /// Responses only make room by dropping progress notifications.
+    DropOldestNotification,
+
+    /// Fail sending with [`Error::QUEUE_FULL`].
+    Fail,
+
+    /// Close the queue, so the transport disconnects the slow peer.
+    /// Sending fails with [`Error::REMOTE_DISCONNECTED`].
+    /// Queued requests and notifications are dropped,
+    /// queued responses and progress notifications are still delivered.
+    Disconnect,
+}
+
+/// Creates a queue holding up to `capacity` responses
+/// and `capacity` other messages.
+pub(crate) fn queue(capacity: usize, policy: QueuePolicy) -> (OutSender, OutReceiver) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            responses: VecDeque::new(),
+            messages: VecDeque::new(),
+            senders: 1,
+            receivers: 1,
+            closed: false,
+        }),
+        capacity: capacity.max(1),
+        policy,
+        recv_ops: Event::new(),
+        send_ops: Event::new(),
+        task_ops: Event::new(),
+        tasks: Default::default(),
+    });
+    let receiver = OutReceiver {
+        shared: shared.clone(),
+        listener: None,
+    };
+    (OutSender { shared }, receiver)
+}
+
+struct Shared {
+    state: Mutex<State>,
+    capacity: usize,
+    policy: QueuePolicy,
+    /// Notified when a message is queued or the queue is closed.
+    recv_ops: Event,
+    /// Notified when a message is taken or the queue is closed.
+    send_ops: Event,
+    /// Notified when a task is added or the queue is closed.
+    task_ops: Event,
+    /// Tasks not yet taken by a [`Forwarder`].
+    tasks: Mutex<Vec<BoxFuture<'static, ()>>>,
+}
+
+impl Shared {
+    /// Closes the queue, returns whether it was open.
+    ///
+    /// Wakes all waiting senders and receivers.
+    fn close(&self, state: &mut State) -> bool {
+        if state.closed {
+            return false;
+        }
+        state.closed = true;
+        self.recv_ops.notify(usize::MAX);
+        self.send_ops.notify(usize::MAX);
+        true
+    }
+
+    /// Drops the tasks not taken yet,
+    /// and wakes the forwarders to drop theirs.
+    fn drop_tasks(&self) {
+        let tasks = std::mem::take(&mut *self.tasks.lock().unwrap());
+        drop(tasks);
+        self.task_ops.notify(usize::MAX);
+    }
+}
+
+/// Kind of a queued message, deciding how it is treated when the queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Request,
+    Notification,
+    /// `$/progress` notification, queued with the responses.
+    Progress,
+    /// Response or batch of responses.
+    Response,
+}
+
+impl Kind {
+    pub(crate) fn of(message: &Message) -> Self {
+        match message {
+            Message::Request(request) if request.id.is_none() && request.method == PROGRESS => {
+                Kind::Progress
+            }
+            Message::Request(request) if request.id.is_none() => Kind::Notification,
+            Message::Request(_) => Kind::Request,
+            Message::Response(_) => Kind::Response,
+            Message::Batch(messages) => match messages.first() {
+                Some(Message::Response(_)) => Kind::Response,
+                _ => Kind::Request,
+            },
+        }
+    }
+
+    /// Whether the message is queued with the responses.
+    fn is_response(self) -> bool {
+        matches!(self, Kind::Response | Kind::Progress)
+    }
+
+    /// Whether the message may be dropped to make room.
+    fn is_notification(self) -> bool {
+        matches!(self, Kind::Notification | Kind::Progress)
+    }
+}
+
+struct State {
+    /// Queued responses and progress notifications, oldest first.
+    responses: VecDeque<(Kind, String)>,
+    /// Queued requests and notifications, oldest first.
+    messages: VecDeque<(Kind, String)>,
+    /// Number of senders alive.
+    senders: usize,
+    /// Number of receivers alive.
+    receivers: usize,
+    /// Whether the queue was closed by a receiver, by dropping all receivers
+    /// or by disconnecting the slow peer.
+    closed: bool,
+}
+
+impl State {
+    /// Returns the queue of messages of `kind`.
+    fn queue(&mut self, kind: Kind) -> &mut VecDeque<(Kind, String)> {
+        match kind.is_response() {
+            true => &mut self.responses,
+            false => &mut self.messages,
+        }
+    }
+
+    /// Takes the next message, responses first.
+    fn pop(&mut self) -> Option<String> {
+        let (_, message) = self
+            .responses
+            .pop_front()
+            .or_else(|| self.messages.pop_front())?;
+        Some(message)
+    }
+
+    /// Returns the number of queued messages.
+    fn len(&self) -> usize {
+        self.responses.len() + self.messages.len()
+    }
+}
+
+/// Removes the oldest notification from `queue`, returns whether there was one.
+fn drop_oldest_notification(queue: &mut VecDeque<(Kind, String)>) -> bool {
+    let position = queue.iter().position(|(kind, _)| kind.is_notification());
+    position
+        .and_then(|position| queue.remove(position))
+        .is_some()
+}
+
+/// Sending half of the outgoing queue, owned by the clients.
+pub(crate) struct OutSender {
+    shared: Arc<Shared>,
+}
+
+impl OutSender {
+    /// Queues the serialized `message` according to the queue policy.
+    pub(crate) async fn send(&self, kind: Kind, message: String) -> Result<(), Error> {
+        let mut message = (kind, message);
+        loop {
+            let listener = {
+                let mut state = self.shared.state.lock().unwrap();
+                match self.push(&mut state, message)? {
+                    None => return Ok(()),
+                    Some(rejected) => message = rejected,
+                }
+                // Listen before unlocking, so that no notification is missed.
+                self.shared.send_ops.listen()
+            };
+            listener.await;
+        }
+    }
+
+    /// Queues `message` without waiting.
+    ///
+    /// Fails with [`Error::QUEUE_FULL`] if the policy would wait for space.
+    pub(crate) fn try_send(&self, kind: Kind, message: String) -> Result<(), Error> {
+        let mut state = self.shared.state.lock().unwrap();
+        match self.push(&mut state, (kind, message))? {
+            None => Ok(()),
+            Some(_) => Err(Error::queue_full()),
+        }
+    }
+
+    /// Adds a task forwarding subscription items,
+    /// run by a [`Forwarder`].
+    ///
+    /// The task is dropped when a receiver closes the queue
+    /// or the last receiver is dropped.
+    pub(crate) fn spawn(&self, task: BoxFuture<'static, ()>) {
+        let mut tasks = self.shared.tasks.lock().unwrap();
+        // Checked with the tasks locked, so closing the queue cannot miss the task.
+        if self.shared.state.lock().unwrap().closed {
+            drop(tasks);
+            drop(task);
+            return;
+        }
+        tasks.push(task);
+        drop(tasks);
+        self.shared.task_ops.notify(1);
+    }
+
+    /// Queues `message` if the policy allows it,
+    /// returns it back if it has to wait for space.
+    fn push(
+        &self,
+        state: &mut State,
+        message: (Kind, String),
+    ) -> Result<Option<(Kind, String)>, Error> {
+        if state.closed {
+            return Err(Error::remote_disconnected());
+        }
+        let queue = state.queue(message.0);
+        if queue.len() >= self.shared.capacity {
+            match self.shared.policy {
+                QueuePolicy::Block => return Ok(Some(message)),
+                QueuePolicy::DropOldestNotification => {
+                    if !drop_oldest_notification(queue) {
+                        return Ok(Some(message));
+                    }
+                }
+                QueuePolicy::Fail => return Err(Error::queue_full()),
+                QueuePolicy::Disconnect => {
+                    state.messages.clear();
+                    self.shared.close(state);
+                    return Err(Error::remote_disconnected());
+                }
+            }
+        }
+        queue.push_back(message);
+        self.shared.recv_ops.notify(1);
+        Ok(None)
+    }
+}
+
+impl Clone for OutSender {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().unwrap().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for OutSender {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            self.shared.recv_ops.notify(usize::MAX);
+        }
+    }
+}
+
+/// Receiving half of the outgoing queue, read by the transport.
+///
+/// Yields serialized messages ready to be sent as text frames.
+/// Items of active subscriptions are forwarded by the future returned by
+/// [`OutReceiver::forward_subscriptions`].
+///
+/// Clones receive from the same queue, each message is taken by one of them.
+/// Ends once all clients are dropped and the queue is empty,
+/// or when the queue is closed and no messages are left.
+/// Dropping the last receiver closes the queue and stops the subscriptions.
+pub struct OutReceiver {
+    shared: Arc<Shared>,
+    listener: Option<EventListener>,
+}
+
+impl OutReceiver {
+    /// Takes the next message, waiting until one is available.
+    pub async fn recv(&self) -> Result<String, RecvError> {
+        let mut listener = None;
+        poll_fn(|cx| self.poll_recv(&mut listener, cx))
+            .await
+            .ok_or(RecvError)
+    }
+
+    /// Takes the next message if one is available.
+    pub fn try_recv(&self) -> Result<String, TryRecvError> {
+        let mut state = self.shared.state.lock().unwrap();
+        match state.pop() {
+            Some(message) => {
+                drop(state);
+                self.shared.send_ops.notify(1);
+                Ok(message)
+            }
+            None if state.closed || state.senders == 0 => Err(TryRecvError::Closed),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Closes the queue and stops the subscriptions.
+    ///
+    /// Sending fails with [`Error::REMOTE_DISCONNECTED`] afterwards,
+    /// messages already queued can still be received.
+    /// Returns `true` if this call closed the queue.
+    pub fn close(&self) -> bool {
+        let closed = self.shared.close(&mut self.shared.state.lock().unwrap());
+        self.shared.drop_tasks();
+        closed
+    }
+
+    /// Returns whether the queue is closed.
+    pub fn is_closed(&self) -> bool {
+        self.shared.state.lock().unwrap().closed
+    }
+
+    /// Returns the number of queued messages.
+    pub fn len(&self) -> usize {
+        self.shared.state.lock().unwrap().len()
+    }
+
+    /// Returns whether no messages are queued.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the capacity of the queue,
+    /// applying to responses and to other messages separately.
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+
+    /// Returns a future running the tasks that forward the items
+    /// of active subscriptions to this queue.
+    ///
+    /// [`serve`](crate::serve) runs it itself, transports reading the queue
+    /// directly have to poll it alongside, for example by spawning it.
+    /// It completes when the queue is closed, stopping the subscriptions.
+    pub fn forward_subscriptions(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut forwarder = self.forwarder();
+        poll_fn(move |cx| forwarder.poll(cx))
+    }
+
+    /// Creates a [`Forwarder`] of this queue.
+    pub(crate) fn forwarder(&self) -> Forwarder {
+        Forwarder {
+            shared: self.shared.clone(),
+            tasks: FuturesUnordered::new(),
+            listener: None,
+        }
+    }
+
+    /// Takes the next message or registers `listener` to wait for one.
+    fn poll_recv(
+        &self,
+        listener: &mut Option<EventListener>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<String>> {
+        loop {
+            if let Some(pending) = listener.as_mut() {
+                futures::ready!(Pin::new(pending).poll(cx));
+                *listener = None;
+            }
+            loop {
+                match self.try_recv() {
+                    Ok(message) => return Poll::Ready(Some(message)),
+                    Err(TryRecvError::Closed) => return Poll::Ready(None),
+                    Err(TryRecvError::Empty) => match listener {
+                        // Check again after listening, then wait.
+                        Some(_) => break,
+                        None => *listener = Some(self.shared.recv_ops.listen()),
+                    },
+                }
+            }
+        }
+    }
+}
+
+impl Stream for OutReceiver {
+    type Item = String;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<String>> {
+        let mut listener = self.listener.take();
+        let res = self.poll_recv(&mut listener, cx);
+        self.listener = listener;
+        res
+    }
+}
+
+impl Clone for OutReceiver {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().unwrap().receivers += 1;
+        Self {
+            shared: self.shared.clone(),
+            listener: None,
+        }
+    }
+}
+
+impl Drop for OutReceiver {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.receivers -= 1;
+        if state.receivers == 0 {
+            self.shared.close(&mut state);
+            drop(state);
+            self.shared.drop_tasks();
+        }
+    }
+}
+
+/// Runner of the tasks forwarding subscription items.
+///
+/// Tasks are polled without holding a lock on the queue,
+/// so a slow subscription stream does not hold up reading.
+pub(crate) struct Forwarder {
+    shared: Arc<Shared>,
+    tasks: FuturesUnordered<BoxFuture<'static, ()>>,
+    listener: Option<EventListener>,
+}
+
+impl Forwarder {
+    /// Takes new tasks from the queue and polls all tasks.
+    ///
+    /// Ready once the queue is closed, dropping the tasks.
+    pub(crate) fn poll(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        loop {
+            // Listen before checking, so that no notification is missed.
+            let listener = self
+                .listener
+                .get_or_insert_with(|| self.shared.task_ops.listen());
+            if self.shared.state.lock().unwrap().closed {
+                self.tasks = FuturesUnordered::new();
+                return Poll::Ready(());
+            }
+            self.tasks
+                .extend(std::mem::take(&mut *self.shared.tasks.lock().unwrap()));
+            while let Poll::Ready(Some(())) = self.tasks.poll_next_unpin(cx) {}
+            futures::ready!(Pin::new(listener).poll(cx));
+            self.listener = None;
+        }
+    }
+
+    /// Returns whether no task is running or waiting to be taken.
+    pub(crate) fn is_idle(&self) -> bool {
+        self.tasks.is_empty() && self.shared.tasks.lock().unwrap().is_empty()
+    }
+}
+
+impl std::fmt::Debug for OutReceiver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OutReceiver")
+            .field("len", &self.len())
+            .field("capacity", &self.shared.capacity)
+            .field("policy", &self.shared.policy)
+            .finish()
+    }
+}
@@ -1,41 +1,230 @@
-use async_mutex::Mutex;
 use futures::channel::oneshot;
+use futures::future::{join_all, select, AbortHandle, Abortable, BoxFuture, Either};
+use futures_timer::Delay;
 use futures_util::{Future, Sink};
-use serde::Serialize;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::value::RawValue;
 use std::io;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     pin::Pin,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
     task::{Context, Poll},
+    time::Duration,
 };
 
-use crate::{Error, Id, Message, Params, Request, Response, RpcServer, Version};
+#[cfg(feature = "anyhow")]
+use crate::anyhow_error::{self, AnyhowConverter};
+use crate::codec::{Codec, Json};
+use crate::context::Metadata;
+use crate::message::{encode, Frame, Incoming, RawResponse};
+use crate::metrics::{CallTimer, Direction, MetricsRecorder};
+use crate::middleware::{Middleware, Next, RpcCall};
+use crate::queue::{queue, Kind, OutSender};
+#[cfg(feature = "tracing")]
+use crate::spans;
+use crate::subscriptions::Subscriptions;
+use crate::{
+    to_raw_result, CancelParams, Error, Id, Message, MessageError, OutReceiver, Params,
+    QueuePolicy, RawParams, RawResult, Request, RequestContext, Response, RpcServer,
+    SubscriptionId, SubscriptionParams, SubscriptionStream, Version, CANCEL_REQUEST,
+    DEFAULT_QUEUE_CAPACITY, UNSUBSCRIBE,
+};
 
 // pub fn create_session(server_impl: impl RpcServer) -> (RpcSession<T>,
 
 pub struct RpcSession<T> {
     client: RpcClient,
     server: T,
+    /// Abort handles of requests being processed, by request ID.
+    running: Arc<Mutex<Running>>,
+    /// Number of requests being processed.
+    in_flight: Arc<AtomicUsize>,
+    /// Maximum number of requests processed concurrently,
+    /// `None` to process incoming messages one after another.
+    max_concurrent_requests: Option<usize>,
+    /// Hook for errors that are not reported to the remote side.
+    error_hook: Option<ErrorHook>,
+    /// Middlewares wrapping the handlers, outermost first.
+    middlewares: Vec<Arc<dyn Middleware>>,
+    /// Connection metadata available to handlers.
+    metadata: Metadata,
+    /// Recorder of incoming call metrics.
+    metrics: Option<Arc<dyn MetricsRecorder>>,
+    /// Policy rendering `anyhow` errors returned by handlers.
+    #[cfg(feature = "anyhow")]
+    anyhow_converter: AnyhowConverter,
+    /// Active subscriptions.
+    subscriptions: Arc<Mutex<Subscriptions>>,
+}
+
+/// Subscriptions started while processing a message, with the ID of their request.
+///
+/// They are registered and forwarded once their responses are queued.
+type PendingSubscriptions = Mutex<Vec<(Id, SubscriptionId, SubscriptionStream)>>;
+
+/// Subscription started by the handler of a request,
+/// kept until the request succeeds.
+type StartedSubscription = Mutex<Option<(SubscriptionId, SubscriptionStream)>>;
+
+/// Error that cannot be reported to the remote side.
+#[derive(Debug)]
+pub enum SessionError {
+    /// Notification handler returned an error.
+    ///
+    /// Notifications are never answered, not even with an error.
+    Notification {
+        /// Method name of the notification.
+        method: String,
+        /// Error returned by the handler.
+        error: Error,
+    },
+
+    /// Response arrived for a request that is not pending.
+    ///
+    /// This happens if the request timed out or was cancelled,
+    /// or if the remote side sent an unexpected response.
+    UnknownResponse(Response),
 }
 
+/// Callback receiving errors that cannot be reported to the remote side.
+pub type ErrorHook = Arc<dyn Fn(SessionError) + Send + Sync>;
+
 impl<T: Clone> Clone for RpcSession<T> {
     fn clone(&self) -> Self {
         Self {
             client: self.client.clone(),
             server: self.server.clone(),
+            running: self.running.clone(),
+            in_flight: self.in_flight.clone(),
+            max_concurrent_requests: self.max_concurrent_requests,
+            error_hook: self.error_hook.clone(),
+            middlewares: self.middlewares.clone(),
+            metadata: self.metadata.clone(),
+            metrics: self.metrics.clone(),
+            #[cfg(feature = "anyhow")]
+            anyhow_converter: self.anyhow_converter,
+            subscriptions: self.subscriptions.clone(),
         }
     }
 }
 
 impl<T: RpcServer> RpcSession<T> {
-    pub fn create(server: T) -> (Self, async_channel::Receiver<Message>) {
+    pub fn create(server: T) -> (Self, OutReceiver) {
         let (client, receiver) = RpcClient::new();
         (Self::new(client, server), receiver)
     }
 
+    /// Returns a builder configuring the outgoing queue of the session.
+    pub fn builder(server: T) -> RpcSessionBuilder<T> {
+        RpcSessionBuilder {
+            server,
+            client: RpcClient::builder(),
+        }
+    }
+
     pub fn new(client: RpcClient, server: T) -> Self {
-        Self { client, server }
+        Self {
+            client,
+            server,
+            running: Default::default(),
+            in_flight: Default::default(),
+            max_concurrent_requests: None,
+            error_hook: None,
+            middlewares: Vec::new(),
+            metadata: Metadata::default(),
+            metrics: None,
+            #[cfg(feature = "anyhow")]
+            anyhow_converter: AnyhowConverter::default(),
+            subscriptions: Default::default(),
+        }
+    }
+
+    /// Sets a hook for errors that cannot be reported to the remote side,
+    /// such as failed notifications and responses to unknown requests.
+    ///
+    /// Without a hook these errors are logged.
+    pub fn with_error_hook(mut self, hook: impl Fn(SessionError) + Send + Sync + 'static) -> Self {
+        self.error_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Adds a middleware wrapping the handling of incoming requests and notifications.
+    ///
+    /// Middlewares run in the order they are added,
+    /// so the first one sees calls first and results last.
+    pub fn with_middleware(mut self, middleware: impl Middleware) -> Self {
+        self.middlewares.push(Arc::new(middleware));
+        self
+    }
+
+    /// Attaches connection metadata, such as the peer address or HTTP headers.
+    ///
+    /// Handlers can extract it with a [`Meta<T>`](crate::Meta) argument
+    /// or from the [`RequestContext`].
+    /// Metadata of the same type replaces the previous value.
+    pub fn with_metadata<M: Send + Sync + 'static>(mut self, metadata: M) -> Self {
+        self.metadata.insert(metadata);
+        self
+    }
+
+    /// Sets the policy rendering `anyhow` errors returned by request and notification handlers.
+    ///
+    /// Defaults to [`AnyhowConverter::default`].
+    #[cfg(feature = "anyhow")]
+    pub fn with_anyhow_converter(mut self, converter: AnyhowConverter) -> Self {
+        self.anyhow_converter = converter;
+        self
+    }
+
+    /// Attaches a recorder of per-method metrics.
+    ///
+    /// The recorder measures the calls handled by the session
+    /// and the requests sent by its client,
+    /// unless a recorder is already attached to the client
+    /// with [`RpcClient::with_metrics`], which is kept.
+    pub fn with_metrics(mut self, recorder: impl MetricsRecorder) -> Self {
+        let recorder: Arc<dyn MetricsRecorder> = Arc::new(recorder);
+        if self.client.metrics.is_none() {
+            self.client.metrics = Some(recorder.clone());
+        }
+        self.metrics = Some(recorder);
+        self
+    }
+
+    /// Passes an error to the error hook.
+    fn report_error(&self, error: SessionError) {
+        match &self.error_hook {
+            Some(hook) => hook(error),
+            None => log::warn!("Unhandled JSON-RPC error: {error:?}"),
+        }
+    }
+
+    /// Enables concurrent dispatch of incoming messages.
+    ///
+    /// Integrations process incoming messages concurrently
+    /// instead of waiting for each handler to finish.
+    /// At most `limit` requests are processed at the same time,
+    /// further requests are rejected with [`Error::SERVER_BUSY`].
+    /// Notifications and responses are not limited,
+    /// so handlers can wait for responses to their own requests.
+    pub fn with_max_concurrent_requests(mut self, limit: usize) -> Self {
+        self.max_concurrent_requests = Some(limit);
+        self
+    }
+
+    /// Returns the maximum number of concurrently processed requests
+    /// if concurrent dispatch is enabled.
+    pub fn max_concurrent_requests(&self) -> Option<usize> {
+        self.max_concurrent_requests
+    }
+
+    /// Returns the number of requests currently being processed.
+    pub fn in_flight_requests(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
     }
 
     /// Returns a reference to the JSON-RPC client.
@@ -54,94 +243,741 @@ impl<T: RpcServer> RpcSession<T> {
 
     /// Processes incoming JSON-RPC message.
     ///
-    /// Handles incoming requests and notifications,
-    /// returns a response if any.
-    pub async fn process_incoming(&self, input: &str) -> Option<Message> {
-        let message: Message = match serde_json::from_str(input) {
-            Ok(message) => message,
-            Err(err) => {
-                return Some(Message::Response(Response::error(
-                    None,
-                    Error::new(Error::PARSE_ERROR, err.to_string()),
-                )));
+    /// Handles incoming requests, notifications and batches,
+    /// returns the serialized response if any.
+    ///
+    /// Subscriptions started by the message are registered when it returns
+    /// and their items are forwarded through the outgoing queue,
+    /// so send the response before reading the queue again
+    /// for it to arrive before the first item.
+    pub async fn process_incoming(&self, input: &str) -> Option<String> {
+        let pending = PendingSubscriptions::default();
+        let response = self.process(input, &pending).await;
+        self.start_subscriptions(pending);
+        response
+    }
+
+    /// Registers subscriptions whose responses are queued and hands the tasks
+    /// forwarding their items to the outgoing queue.
+    fn start_subscriptions(&self, pending: PendingSubscriptions) {
+        for (_, id, stream) in pending.into_inner().unwrap() {
+            let task = Subscriptions::start(&self.subscriptions, id, stream, self.client.clone());
+            self.client.tx.spawn(task);
+        }
+    }
+
+    /// Stops all active subscriptions, e.g. when the connection ends.
+    ///
+    /// Each one ends with a `$/subscriptionEnd` notification.
+    pub fn stop_subscriptions(&self) {
+        self.subscriptions.lock().unwrap().stop_all();
+    }
+
+    /// Processes incoming JSON-RPC message,
+    /// collecting subscriptions started by successful requests in `pending`.
+    async fn process(&self, input: &str, pending: &PendingSubscriptions) -> Option<String> {
+        match Frame::parse(input) {
+            Ok(Frame::Single(Ok(message))) => {
+                let response = self.process_message(message, pending).await?;
+                encode_response(response, pending)
             }
-        };
+            Ok(Frame::Single(Err(err))) | Err(err) => {
+                encode_response(RawResponse::error(err.id, err.error), pending)
+            }
+            Ok(Frame::Batch(messages)) if messages.is_empty() => {
+                encode_response(RawResponse::error(None, Error::invalid_request()), pending)
+            }
+            Ok(Frame::Batch(messages)) => {
+                let responses = self.process_batch(messages, pending).await?;
+                let responses: Vec<String> = responses
+                    .into_iter()
+                    .filter_map(|response| encode_response(response, pending))
+                    .collect();
+                Some(format!("[{}]", responses.join(",")))
+            }
+        }
+    }
 
+    /// Processes a batch of JSON-RPC messages.
+    ///
+    /// Messages of the batch are processed concurrently.
+    /// Invalid messages are answered with an error each.
+    /// Returns all responses, if any.
+    async fn process_batch(
+        &self,
+        messages: Vec<Result<Incoming, MessageError>>,
+        pending: &PendingSubscriptions,
+    ) -> Option<Vec<RawResponse>> {
+        // Responses to a batch sent by our client are routed together.
+        let mut requests = Vec::new();
+        let mut responses = Vec::new();
+        let mut errors = Vec::new();
+        for message in messages {
+            match message {
+                Ok(Incoming::Response(response, size)) => responses.push((response, size)),
+                Ok(message) => requests.push(message),
+                Err(err) => errors.push(RawResponse::error(err.id, err.error)),
+            }
+        }
+        if !responses.is_empty() {
+            let unknown = self
+                .client
+                .inner
+                .lock()
+                .unwrap()
+                .handle_responses(responses);
+            for response in unknown {
+                self.report_error(SessionError::UnknownResponse(response));
+            }
+        }
+        let mut responses: Vec<RawResponse> = join_all(
+            requests
+                .into_iter()
+                .map(|message| self.process_message(message, pending)),
+        )
+        .await
+        .into_iter()
+        .flatten()
+        .collect();
+        responses.append(&mut errors);
+        if responses.is_empty() {
+            None
+        } else {
+            Some(responses)
+        }
+    }
+
+    /// Processes a single JSON-RPC message that is not a batch.
+    async fn process_message(
+        &self,
+        message: Incoming,
+        pending: &PendingSubscriptions,
+    ) -> Option<RawResponse> {
         match message {
-            Message::Request(request) => {
-                let params = request.params.map(Params::into_value).unwrap_or_default();
-                let response = match request.id {
-                    None => {
-                        match self
-                            .server
-                            .handle_notification(request.method, params)
-                            .await
-                        {
-                            Ok(()) => None,
-                            Err(err) => Some(Response::error(request.id, err)),
+            Incoming::Request(request) => {
+                let params = request.params;
+                #[cfg(feature = "tracing")]
+                let span = spans::incoming(&request.method, request.id.as_ref(), &params);
+                let response = async move {
+                    match request.id {
+                        None if request.method == CANCEL_REQUEST => {
+                            self.cancel_running(params);
+                            None
                         }
+                        None => {
+                            self.process_notification(request.method, params).await;
+                            None
+                        }
+                        Some(id) => Some(
+                            self.process_request(id, request.method, params, pending)
+                                .await,
+                        ),
                     }
-                    Some(id) => match self.server.handle_request(request.method, params).await {
-                        Ok(payload) => Some(Response::success(id, payload)),
-                        Err(err) => Some(Response::error(Some(id), err)),
-                    },
                 };
-                response.map(Message::Response)
+                #[cfg(feature = "tracing")]
+                let response = tracing::Instrument::instrument(response, span);
+                response.await
             }
-            Message::Response(response) => {
-                self.client.handle_response(response).await;
+            Incoming::Response(response, size) => {
+                let unknown = self
+                    .client
+                    .inner
+                    .lock()
+                    .unwrap()
+                    .handle_response(response, size);
+                if let Some(response) = unknown {
+                    self.report_error(SessionError::UnknownResponse(response));
+                }
                 None
             }
         }
     }
 
+    /// Processes a notification, reporting errors to the error hook.
+    async fn process_notification(&self, method: String, params: RawParams) {
+        let timer = self.start_call(&method, &params);
+        let call = RpcCall {
+            id: None,
+            method: method.clone(),
+            params,
+        };
+        let endpoint = |call: RpcCall| -> BoxFuture<'_, crate::Result<RawResult>> {
+            Box::pin(async move {
+                let context = self.context(None, &call.method);
+                self.server
+                    .handle_notification_raw(call.method, call.params, context)
+                    .await?;
+                to_raw_result(&())
+            })
+        };
+        let res = Next::new(&self.middlewares, &endpoint).run(call);
+        #[cfg(feature = "anyhow")]
+        let (res, captured) = anyhow_error::capture(res).await;
+        #[cfg(not(feature = "anyhow"))]
+        let res = res.await;
+        #[cfg(feature = "tracing")]
+        spans::record_outcome(res.as_ref().err());
+        if let Some(timer) = timer {
+            timer.finish(res.as_ref().err(), 0);
+        }
+        if let Err(error) = res {
+            #[cfg(feature = "anyhow")]
+            let error = self.anyhow_converter.apply(error, &captured);
+            self.report_error(SessionError::Notification { method, error });
+        }
+    }
+
+    /// Processes a request and returns the response.
+    async fn process_request(
+        &self,
+        id: Id,
+        method: String,
+        params: RawParams,
+        pending: &PendingSubscriptions,
+    ) -> RawResponse {
+        if method == UNSUBSCRIBE {
+            return self.unsubscribe(id, params);
+        }
+        let timer = self.start_call(&method, &params);
+        let response = match self.try_acquire_permit() {
+            Some(_permit) => self.run_request(id, method, params, pending).await,
+            None => RawResponse::error(Some(id), Error::server_busy()),
+        };
+        #[cfg(feature = "tracing")]
+        spans::record_outcome(response.error.as_ref());
+        if let Some(timer) = timer {
+            let result_bytes = response
+                .result
+                .as_ref()
+                .map_or(0, |result| result.get().len());
+            timer.finish(response.error.as_ref(), result_bytes);
+        }
+        response
+    }
+
+    /// Runs a request through the middlewares and the handler,
+    /// unless it is cancelled.
+    async fn run_request(
+        &self,
+        id: Id,
+        method: String,
+        params: RawParams,
+        pending: &PendingSubscriptions,
+    ) -> RawResponse {
+        let (handle, registration) = AbortHandle::new_pair();
+        // Requests with a `null` ID cannot be cancelled, so they are not registered.
+        let _running = if id == Id::Null {
+            None
+        } else {
+            let Some(token) = self.running.lock().unwrap().register(&id, handle) else {
+                let error = Error::new(
+                    Error::INVALID_REQUEST,
+                    "Request ID is already in use".to_string(),
+                );
+                return RawResponse::error(Some(id), error);
+            };
+            // Unregisters the request even if this future is dropped.
+            Some(RunningRequest {
+                running: &self.running,
+                id: id.clone(),
+                token,
+            })
+        };
+        let call = RpcCall {
+            id: Some(id.clone()),
+            method,
+            params,
+        };
+        let request_id = &id;
+        let started = StartedSubscription::default();
+        let started_ref = &started;
+        let endpoint = move |call: RpcCall| -> BoxFuture<'_, crate::Result<RawResult>> {
+            Box::pin(self.dispatch(request_id, call.method, call.params, started_ref))
+        };
+        let next = Next::new(&self.middlewares, &endpoint);
+        let res = Abortable::new(next.run(call), registration);
+        #[cfg(feature = "anyhow")]
+        let (res, captured) = anyhow_error::capture(res).await;
+        #[cfg(not(feature = "anyhow"))]
+        let res = res.await;
+        match res {
+            Ok(Ok(payload)) => {
+                // Subscriptions of failed or cancelled requests never start.
+                if let Some((subscription, stream)) = started.into_inner().unwrap() {
+                    pending
+                        .lock()
+                        .unwrap()
+                        .push((id.clone(), subscription, stream));
+                }
+                RawResponse::success(id, payload)
+            }
+            Ok(Err(err)) => {
+                #[cfg(feature = "anyhow")]
+                let err = self.anyhow_converter.apply(err, &captured);
+                RawResponse::error(Some(id), err)
+            }
+            Err(_aborted) => RawResponse::error(Some(id), Error::request_cancelled()),
+        }
+    }
+
+    /// Starts measuring an incoming call if a metrics recorder is attached.
+    fn start_call(&self, method: &str, params: &RawParams) -> Option<CallTimer> {
+        let recorder = self.metrics.as_ref()?;
+        let params_bytes = params.as_ref().map_or(0, |params| params.get().len());
+        Some(CallTimer::start(
+            recorder,
+            Direction::Incoming,
+            method,
+            params_bytes,
+        ))
+    }
+
+    /// Calls the request or subscription handler for `method`.
+    ///
+    /// Subscriptions respond with the subscription ID,
+    /// their stream is kept in `started`.
+    async fn dispatch(
+        &self,
+        id: &Id,
+        method: String,
+        params: RawParams,
+        started: &StartedSubscription,
+    ) -> crate::Result<RawResult> {
+        let context = self.context(Some(id.clone()), &method);
+        if !self.server.is_subscription(&method) {
+            return self
+                .server
+                .handle_request_raw(method, params, context)
+                .await;
+        }
+        let stream = self
+            .server
+            .handle_subscription(method, params, context)
+            .await?;
+        let subscription = self.subscriptions.lock().unwrap().next_id();
+        *started.lock().unwrap() = Some((subscription, stream));
+        to_raw_result(&subscription)
+    }
+
+    /// Creates the context of a request with `id` to `method`.
+    fn context(&self, id: Option<Id>, method: &str) -> RequestContext {
+        RequestContext::in_session(
+            id,
+            method.to_string(),
+            self.client.clone(),
+            self.metadata.clone(),
+        )
+    }
+
+    /// Stops the subscription referenced by `$/unsubscribe` params.
+    ///
+    /// Responds with false if there is no such subscription.
+    fn unsubscribe(&self, id: Id, params: RawParams) -> RawResponse {
+        let stopped = match decode_params::<SubscriptionParams>(&params) {
+            Ok(SubscriptionParams { subscription }) => {
+                self.subscriptions.lock().unwrap().stop(subscription)
+            }
+            Err(err) => {
+                let error = Error::new(Error::INVALID_PARAMS, err.to_string());
+                return RawResponse::error(Some(id), error);
+            }
+        };
+        match to_raw_result(&stopped) {
+            Ok(result) => RawResponse::success(id, result),
+            Err(err) => RawResponse::error(Some(id), err),
+        }
+    }
+
+    /// Counts a new request as being processed
+    /// unless the concurrency limit is reached.
+    fn try_acquire_permit(&self) -> Option<RequestPermit> {
+        self.in_flight
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                match self.max_concurrent_requests {
+                    Some(limit) if n >= limit => None,
+                    _ => Some(n + 1),
+                }
+            })
+            .ok()?;
+        Some(RequestPermit(self.in_flight.clone()))
+    }
+
+    /// Aborts the request handler referenced by `$/cancelRequest` params.
+    ///
+    /// Unknown or already finished requests are ignored.
+    fn cancel_running(&self, params: RawParams) {
+        if let Ok(CancelParams { id }) = decode_params(&params) {
+            self.running.lock().unwrap().abort(&id);
+        }
+    }
+
     /// Handles incoming JSON-RPC request.
     ///
     /// Sends response to the client.
     /// Blocks until request handler finishes.
     /// Spawn a task if you want to run the request handler
-    /// concurrently.
+    /// concurrently, or enable concurrent dispatch
+    /// with [`RpcSession::with_max_concurrent_requests`]
+    /// when using one of the integrations.
+    /// Subscriptions started by the message are registered
+    /// once the response is queued, then their items are forwarded.
     pub async fn handle_incoming(&self, input: &str) {
-        if let Some(response) = self.process_incoming(input).await {
-            let _ = self.client.tx(response).await;
+        let pending = PendingSubscriptions::default();
+        if let Some(response) = self.process(input, &pending).await {
+            if self.client.tx.send(Kind::Response, response).await.is_err() {
+                return;
+            }
+        }
+        self.start_subscriptions(pending);
+    }
+
+    /// Handles an incoming frame encoded with `codec`,
+    /// like [`RpcSession::handle_incoming`].
+    ///
+    /// Frames that cannot be decoded are answered with [`Error::PARSE_ERROR`].
+    pub async fn handle_incoming_frame(&self, codec: &dyn Codec, frame: Vec<u8>) {
+        match codec.decode(frame) {
+            Ok(input) => self.handle_incoming(&input).await,
+            Err(err) => self.client.send_error(err).await,
+        }
+    }
+}
+
+/// Counts a request as being processed until dropped.
+struct RequestPermit(Arc<AtomicUsize>);
+
+impl Drop for RequestPermit {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Abort handles of requests being processed.
+#[derive(Default)]
+struct Running {
+    /// Token of the next registration.
+    next_token: u64,
+    /// Registration token and abort handle, by request ID.
+    handles: HashMap<Id, (u64, AbortHandle)>,
+}
+
+impl Running {
+    /// Registers a request with `id`, returning its token,
+    /// or `None` if another request with `id` is running.
+    fn register(&mut self, id: &Id, handle: AbortHandle) -> Option<u64> {
+        if self.handles.contains_key(id) {
+            return None;
+        }
+        let token = self.next_token;
+        self.next_token = self.next_token.wrapping_add(1);
+        self.handles.insert(id.clone(), (token, handle));
+        Some(token)
+    }
+
+    /// Unregisters the request with `id` if it still has `token`.
+    ///
+    /// A cancelled request is unregistered before its handler is dropped,
+    /// so a new request may have reused its ID in the meantime.
+    fn unregister(&mut self, id: &Id, token: u64) {
+        if matches!(self.handles.get(id), Some((registered, _)) if *registered == token) {
+            self.handles.remove(id);
+        }
+    }
+
+    /// Aborts and unregisters the request with `id`, if any.
+    fn abort(&mut self, id: &Id) {
+        if let Some((_, handle)) = self.handles.remove(id) {
+            handle.abort();
         }
     }
 }
 
+/// Removes a request from the running requests when dropped.
+struct RunningRequest<'a> {
+    running: &'a Mutex<Running>,
+    id: Id,
+    token: u64,
+}
+
+impl Drop for RunningRequest<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut running) = self.running.lock() {
+            running.unregister(&self.id, self.token);
+        }
+    }
+}
+
+/// Builder of an [`RpcSession`] with a configured outgoing queue.
+pub struct RpcSessionBuilder<T> {
+    server: T,
+    client: RpcClientBuilder,
+}
+
+impl<T: RpcServer> RpcSessionBuilder<T> {
+    /// Sets the number of outgoing messages queued
+    /// before the [`QueuePolicy`] applies, 10 by default.
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.client = self.client.queue_capacity(capacity);
+        self
+    }
+
+    /// Sets the behavior when the outgoing queue is full.
+    pub fn queue_policy(mut self, policy: QueuePolicy) -> Self {
+        self.client = self.client.queue_policy(policy);
+        self
+    }
+
+    /// Sets the preferred codec, [`Json`] by default.
+    pub fn codec(mut self, codec: impl Codec) -> Self {
+        self.client = self.client.codec(codec);
+        self
+    }
+
+    /// Creates the session and the receiver of its outgoing messages.
+    pub fn build(self) -> (RpcSession<T>, OutReceiver) {
+        let (client, receiver) = self.client.build();
+        (RpcSession::new(client, self.server), receiver)
+    }
+}
+
+/// Builder of an [`RpcClient`] with a configured outgoing queue.
+#[derive(Clone)]
+pub struct RpcClientBuilder {
+    capacity: usize,
+    policy: QueuePolicy,
+    codec: Arc<dyn Codec>,
+}
+
+impl std::fmt::Debug for RpcClientBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RpcClientBuilder")
+            .field("capacity", &self.capacity)
+            .field("policy", &self.policy)
+            .field("codec", &self.codec.subprotocol())
+            .finish()
+    }
+}
+
+impl RpcClientBuilder {
+    /// Sets the number of outgoing messages queued
+    /// before the [`QueuePolicy`] applies, 10 by default.
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Sets the behavior when the outgoing queue is full.
+    pub fn queue_policy(mut self, policy: QueuePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Sets the preferred codec, [`Json`] by default.
+    ///
+    /// Transports that negotiate the codec, such as the WebSocket integrations,
+    /// use it if the peer supports it. Other transports always use it.
+    pub fn codec(mut self, codec: impl Codec) -> Self {
+        self.codec = Arc::new(codec);
+        self
+    }
+
+    /// Creates the client and the receiver of its outgoing messages.
+    pub fn build(self) -> (RpcClient, OutReceiver) {
+        let (tx, rx) = queue(self.capacity, self.policy);
+        let client = RpcClient {
+            inner: Arc::new(Mutex::new(PendingRequests::new())),
+            tx,
+            timeout: None,
+            metrics: None,
+            codec: self.codec,
+        };
+        (client, rx)
+    }
+}
+
 #[derive(Clone)]
 pub struct RpcClient {
     inner: Arc<Mutex<PendingRequests>>,
-    tx: async_channel::Sender<Message>,
+    tx: OutSender,
+    /// Default timeout for requests, `None` to wait forever.
+    timeout: Option<Duration>,
+    /// Recorder of outgoing request metrics.
+    metrics: Option<Arc<dyn MetricsRecorder>>,
+    /// Preferred codec of the transport.
+    codec: Arc<dyn Codec>,
 }
 
-pub type OutReceiver = async_channel::Receiver<Message>;
-
 impl RpcClient {
-    pub fn new() -> (Self, async_channel::Receiver<Message>) {
-        let (tx, rx) = async_channel::bounded(10);
-        let inner = PendingRequests::new();
-        let inner = Arc::new(Mutex::new(inner));
-        let this = Self { inner, tx };
-        (this, rx)
+    pub fn new() -> (Self, OutReceiver) {
+        Self::builder().build()
     }
+
+    /// Returns a builder configuring the outgoing queue of the client.
+    pub fn builder() -> RpcClientBuilder {
+        RpcClientBuilder {
+            capacity: DEFAULT_QUEUE_CAPACITY,
+            policy: QueuePolicy::default(),
+            codec: Arc::new(Json),
+        }
+    }
+
+    /// Sets the default timeout for requests sent with this client.
+    ///
+    /// Requests that do not receive a response in time
+    /// fail with [`Error::TIMEOUT`].
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Attaches a recorder of per-method metrics of the requests sent with this client.
+    pub fn with_metrics(mut self, recorder: impl MetricsRecorder) -> Self {
+        self.metrics = Some(Arc::new(recorder));
+        self
+    }
+
+    /// Returns the preferred codec of the transport.
+    pub fn codec(&self) -> &Arc<dyn Codec> {
+        &self.codec
+    }
+
+    /// Returns the default timeout for requests.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Answers a message that could not be read with `error`.
+    ///
+    /// The response has a `null` id, the id of an unreadable message is unknown.
+    pub(crate) async fn send_error(&self, error: Error) {
+        if let Ok(response) = encode(&RawResponse::error(None, error)) {
+            let _ = self.tx.send(Kind::Response, response).await;
+        }
+    }
+
     pub async fn send_request(
         &self,
         method: impl ToString,
         params: Option<impl Serialize>,
     ) -> Result<serde_json::Value, Error> {
+        self.send_request_with_timeout(method, params, self.timeout)
+            .await
+    }
+
+    /// Sends a request and waits for the response
+    /// at most for the given timeout.
+    ///
+    /// If `timeout` is `None`, waits until the response arrives.
+    /// On timeout the request is cancelled.
+    pub async fn send_request_with_timeout(
+        &self,
+        method: impl ToString,
+        params: Option<impl Serialize>,
+        timeout: Option<Duration>,
+    ) -> Result<serde_json::Value, Error> {
+        let method = method.to_string();
+        #[cfg(feature = "tracing")]
+        let span = spans::outgoing(&method);
+        let timer = self
+            .metrics
+            .as_ref()
+            .map(|recorder| CallTimer::start(recorder, Direction::Outgoing, &method, 0));
+        let res = async {
+            // Sizes of the request and response messages.
+            let mut sizes = (0, 0);
+            let res = async {
+                let mut response = self.send_request_message(method, params).await?;
+                sizes.0 = response.request_bytes;
+                let res = (&mut response).await;
+                sizes.1 = response.response_bytes;
+                res
+            };
+            let res = match timeout {
+                Some(timeout) => with_timeout(res, timeout).await,
+                None => res.await,
+            };
+            #[cfg(feature = "tracing")]
+            spans::record_outcome(res.as_ref().err());
+            if let Some(mut timer) = timer {
+                timer.set_params_bytes(sizes.0);
+                timer.finish(res.as_ref().err(), sizes.1);
+            }
+            res
+        };
+        #[cfg(feature = "tracing")]
+        let res = tracing::Instrument::instrument(res, span);
+        res.await
+    }
+
+    /// Sends a request without waiting for the response.
+    ///
+    /// Returns a future that resolves to the result of the request.
+    /// Dropping the future before the response arrives cancels the request.
+    /// With the `tracing` feature the request is sent inside its own `rpc` span.
+    pub async fn start_request(
+        &self,
+        method: impl ToString,
+        params: Option<impl Serialize>,
+    ) -> Result<ResponseFuture, Error> {
         let method = method.to_string();
+        #[cfg(feature = "tracing")]
+        let span = spans::outgoing(&method);
+        let res = self.send_request_message(method, params);
+        #[cfg(feature = "tracing")]
+        let res = tracing::Instrument::instrument(res, span);
+        res.await
+    }
+
+    /// Sends a request, like [`RpcClient::start_request`].
+    ///
+    /// Must run inside the outgoing span of the request,
+    /// which receives its ID and params size.
+    async fn send_request_message(
+        &self,
+        method: String,
+        params: Option<impl Serialize>,
+    ) -> Result<ResponseFuture, Error> {
         let params = downcast_params(params)?;
-        let (message, rx) = self.inner.lock().await.insert(method, params);
-        self.tx(message).await?;
-        // Wait for response to arrive.
-        // TODO: Better error.
-        let res = rx.await.map_err(|_| Error::bad_response())?;
-        match (res.result, res.error) {
-            (Some(result), None) => Ok(result),
-            (None, Some(error)) => Err(error),
-            // TODO: better error.
-            _ => Err(Error::bad_response()),
+        let (request, rx) = self.inner.lock().unwrap().insert(method, params);
+        let id = request.id.clone().expect("requests always have an ID");
+        #[cfg(feature = "tracing")]
+        {
+            let span = tracing::Span::current();
+            spans::record_id(&span, &id);
+            spans::record_params_size(&span, &request.params);
+        }
+        // Constructed before sending, so that a dropped send cleans up too.
+        let mut response = ResponseFuture {
+            client: self.clone(),
+            id,
+            rx,
+            done: false,
+            sent: Default::default(),
+            request_bytes: 0,
+            response_bytes: 0,
+        };
+        let request = encode(&Message::Request(request))?;
+        response.request_bytes = request.len();
+        self.tx.send(Kind::Request, request).await?;
+        response.sent.store(true, Ordering::SeqCst);
+        Ok(response)
+    }
+
+    /// Cancels a pending request.
+    ///
+    /// The request fails with [`Error::REQUEST_CANCELLED`]
+    /// and the remote side is sent a `$/cancelRequest` notification.
+    /// Does nothing if the request is not pending.
+    pub async fn cancel_request(&self, id: &Id) -> Result<(), Error> {
+        let tx = self.inner.lock().unwrap().remove(id);
+        if let Some(tx) = tx {
+            let _ = tx.send((
+                Response::error(Some(id.clone()), Error::request_cancelled()),
+                0,
+            ));
+            self.tx(cancel_notification(id.clone())).await?;
         }
+        Ok(())
     }
 
     pub async fn send_notification(
@@ -162,22 +998,61 @@ impl RpcClient {
         Ok(())
     }
 
+    /// Creates a new batch of requests and notifications
+    /// to be sent together in a single message.
+    pub fn batch(&self) -> RpcBatch {
+        RpcBatch {
+            client: self.clone(),
+            messages: Vec::new(),
+            ids: Vec::new(),
+            sent: Default::default(),
+        }
+    }
+
     pub(crate) async fn tx(&self, message: Message) -> Result<(), Error> {
-        self.tx
-            .send(message)
-            .await
-            .map_err(|_| Error::remote_disconnected())?;
-        Ok(())
+        self.tx.send(Kind::of(&message), encode(&message)?).await
+    }
+
+    /// Handles a response to a request sent by this client.
+    ///
+    /// Returns the response back if no request with its ID is pending.
+    pub async fn handle_response(&self, response: Response) -> Option<Response> {
+        let size = self.response_size(&response);
+        self.inner.lock().unwrap().handle_response(response, size)
     }
 
-    pub async fn handle_response(&self, response: Response) {
-        self.inner.lock().await.handle_response(response)
+    /// Handles responses received in a batch.
+    ///
+    /// Returns responses for which no request is pending.
+    pub async fn handle_responses(&self, responses: Vec<Response>) -> Vec<Response> {
+        let responses: Vec<_> = responses
+            .into_iter()
+            .map(|response| {
+                let size = self.response_size(&response);
+                (response, size)
+            })
+            .collect();
+        self.inner.lock().unwrap().handle_responses(responses)
+    }
+
+    /// Measures a response that was decoded by the caller, only if metrics are recorded.
+    fn response_size(&self, response: &Response) -> usize {
+        match self.metrics {
+            Some(_) => encode(response).map_or(0, |response| response.len()),
+            None => 0,
+        }
     }
 }
 
+/// Response to a pending request with the size of the response message in bytes,
+/// 0 for errors created locally.
+type Reply = (Response, usize);
+
 pub struct PendingRequests {
-    next_request_id: u32,
-    pending_requests: HashMap<Id, oneshot::Sender<Response>>,
+    next_request_id: i64,
+    pending_requests: HashMap<Id, oneshot::Sender<Reply>>,
+    /// Batches with pending requests, oldest first.
+    batches: Vec<PendingBatch>,
     // tx: async_channel::Sender<Message>,
 }
 
@@ -186,33 +1061,422 @@ impl PendingRequests {
         Self {
             next_request_id: 1,
             pending_requests: Default::default(),
+            batches: Vec::new(),
         }
     }
     pub fn insert(
         &mut self,
         method: String,
         params: Option<Params>,
-    ) -> (Message, oneshot::Receiver<Response>) {
-        let request_id = Id::Number(self.next_request_id);
-        self.next_request_id += 1;
+    ) -> (Request, oneshot::Receiver<Reply>) {
         let (tx, rx) = oneshot::channel();
+        let request = self.insert_sender(method, params, tx);
+        (request, rx)
+    }
+
+    /// Removes a pending request without waiting for its response.
+    ///
+    /// Returns the response sender if the request was pending.
+    pub fn remove(&mut self, id: &Id) -> Option<oneshot::Sender<Reply>> {
+        self.unbatch(id, false);
+        self.pending_requests.remove(id)
+    }
+
+    /// Registers pending requests sent together in a batch.
+    fn insert_batch(&mut self, ids: &[Id]) {
+        let ids: HashSet<Id> = ids
+            .iter()
+            .filter(|id| self.pending_requests.contains_key(id))
+            .cloned()
+            .collect();
+        if !ids.is_empty() {
+            self.batches.push(PendingBatch {
+                ids,
+                answered: false,
+            });
+        }
+    }
+
+    /// Removes a request from its batch, if any,
+    /// marking the batch as answered if the request got a response.
+    fn unbatch(&mut self, id: &Id, answered: bool) {
+        let Some(index) = self.batches.iter().position(|batch| batch.ids.contains(id)) else {
+            return;
+        };
+        let batch = &mut self.batches[index];
+        batch.ids.remove(id);
+        batch.answered |= answered;
+        if batch.ids.is_empty() {
+            self.batches.remove(index);
+        }
+    }
+
+    /// Allocates a request ID and registers the sender for the response.
+    fn insert_sender(
+        &mut self,
+        method: String,
+        params: Option<Params>,
+        tx: oneshot::Sender<Reply>,
+    ) -> Request {
+        // IDs wrap around, skipping those of requests still pending.
+        let request_id = loop {
+            let id = Id::Number(self.next_request_id);
+            self.next_request_id = self.next_request_id.wrapping_add(1);
+            if !self.pending_requests.contains_key(&id) {
+                break id;
+            }
+        };
         self.pending_requests.insert(request_id.clone(), tx);
-        let request = Request {
+        Request {
             jsonrpc: Some(Version::V2),
             method,
             params,
             id: Some(request_id),
+        }
+    }
+    /// Passes the response to its pending request.
+    ///
+    /// Returns the response back if no request with its ID is pending.
+    /// An error without ID fails the requests of a batch rejected as a whole
+    /// if it is the only batch none of whose requests were answered.
+    pub fn handle_response(&mut self, response: Response, size: usize) -> Option<Response> {
+        match response.id {
+            Some(_) => self.route(response, size),
+            None => self.fail_batch(response),
+        }
+    }
+
+    /// Passes a response with an ID to its pending request.
+    ///
+    /// Returns the response back if no request with its ID is pending.
+    fn route(&mut self, response: Response, size: usize) -> Option<Response> {
+        let Some(id) = &response.id else {
+            return Some(response);
         };
-        let message = Message::Request(request);
-        (message, rx)
+        let Some(tx) = self.pending_requests.remove(id) else {
+            return Some(response);
+        };
+        self.unbatch(id, true);
+        let _ = tx.send((response, size));
+        None
+    }
+
+    /// Fails the pending requests of a batch rejected as a whole
+    /// with the error of `response`.
+    ///
+    /// A rejected batch gets no other response, so the error matches
+    /// the batch if it is the only one none of whose requests were answered.
+    /// Returns the response back if it is not an error or matches no batch.
+    fn fail_batch(&mut self, response: Response) -> Option<Response> {
+        let Some(error) = &response.error else {
+            return Some(response);
+        };
+        let mut unanswered = self
+            .batches
+            .iter()
+            .enumerate()
+            .filter(|(_, batch)| !batch.answered);
+        let index = match (unanswered.next(), unanswered.next()) {
+            (Some((index, _)), None) => index,
+            _ => return Some(response),
+        };
+        for id in self.batches.remove(index).ids {
+            if let Some(tx) = self.pending_requests.remove(&id) {
+                let error = Error::with_data(error.code, error.message.clone(), error.data.clone());
+                let _ = tx.send((Response::error(Some(id), error), 0));
+            }
+        }
+        None
+    }
+
+    /// Routes each response of a batch to its pending request by ID.
+    ///
+    /// Returns responses for which no request is pending,
+    /// including those without ID, which answer invalid elements
+    /// of a batch rather than rejecting it as a whole.
+    pub fn handle_responses(
+        &mut self,
+        responses: impl IntoIterator<Item = Reply>,
+    ) -> Vec<Response> {
+        responses
+            .into_iter()
+            .filter_map(|(response, size)| self.route(response, size))
+            .collect()
+    }
+}
+
+/// Requests sent together in a batch that are still pending.
+struct PendingBatch {
+    ids: HashSet<Id>,
+    /// Whether one of the requests got a response,
+    /// so the batch was not rejected as a whole.
+    answered: bool,
+}
+
+/// Future resolving to the result of a request.
+///
+/// Created with [`RpcClient::start_request`].
+/// If dropped before the response arrives, the pending request is removed
+/// and a `$/cancelRequest` notification is sent.
+pub struct ResponseFuture {
+    client: RpcClient,
+    id: Id,
+    rx: oneshot::Receiver<Reply>,
+    done: bool,
+    /// Whether the request was handed to the outgoing queue.
+    /// Requests that never went out are not cancelled remotely.
+    sent: Arc<AtomicBool>,
+    /// Size of the request message, 0 if sent in a batch.
+    request_bytes: usize,
+    /// Size of the response message once it arrived.
+    response_bytes: usize,
+}
+
+impl ResponseFuture {
+    /// Returns the ID of the request.
+    pub fn id(&self) -> &Id {
+        &self.id
+    }
+}
+
+impl Future for ResponseFuture {
+    type Output = Result<serde_json::Value, Error>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let res = match Pin::new(&mut self.rx).poll(cx) {
+            Poll::Ready(res) => res,
+            Poll::Pending => return Poll::Pending,
+        };
+        self.done = true;
+        // TODO: Better error.
+        Poll::Ready(
+            res.map_err(|_| Error::bad_response())
+                .and_then(|(response, size)| {
+                    self.response_bytes = size;
+                    response_into_result(response)
+                }),
+        )
+    }
+}
+
+impl Drop for ResponseFuture {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let tx = match self.client.inner.lock() {
+            Ok(mut inner) => inner.remove(&self.id),
+            Err(_) => None,
+        };
+        if tx.is_some() && self.sent.load(Ordering::SeqCst) {
+            // Cannot wait in drop, so the notification is lost if the queue is full.
+            let notification = cancel_notification(self.id.clone());
+            if let Ok(notification) = encode(&notification) {
+                let _ = self.client.tx.try_send(Kind::Notification, notification);
+            }
+        }
+    }
+}
+
+/// Batch of requests and notifications.
+///
+/// Created with [`RpcClient::batch`].
+/// Nothing is sent until [`RpcBatch::send`] is called,
+/// dropping the batch instead discards its requests.
+pub struct RpcBatch {
+    client: RpcClient,
+    messages: Vec<Message>,
+    /// IDs of the queued requests, pending until the batch is sent.
+    ids: Vec<Id>,
+    /// Set once the batch is sent, shared with its responses.
+    sent: Arc<AtomicBool>,
+}
+
+impl RpcBatch {
+    /// Queues a request.
+    ///
+    /// Returns a future that resolves to the result of the request
+    /// once the batch is sent and the response arrives.
+    /// The default timeout of the client applies from the first poll.
+    /// Dropping the future before the response arrives cancels the request.
+    pub fn request(
+        &mut self,
+        method: impl ToString,
+        params: Option<impl Serialize>,
+    ) -> Result<BatchResponse, Error> {
+        let params = downcast_params(params)?;
+        let (request, rx) = self
+            .client
+            .inner
+            .lock()
+            .unwrap()
+            .insert(method.to_string(), params);
+        let id = request.id.clone().expect("requests always have an ID");
+        self.messages.push(Message::Request(request));
+        self.ids.push(id.clone());
+        Ok(BatchResponse {
+            response: ResponseFuture {
+                client: self.client.clone(),
+                id,
+                rx,
+                done: false,
+                sent: self.sent.clone(),
+                request_bytes: 0,
+                response_bytes: 0,
+            },
+            timeout: self.client.timeout,
+            delay: None,
+        })
+    }
+
+    /// Queues a notification.
+    pub fn notification(
+        &mut self,
+        method: impl ToString,
+        params: Option<impl Serialize>,
+    ) -> Result<(), Error> {
+        let params = downcast_params(params)?;
+        self.messages.push(Message::Request(Request {
+            jsonrpc: Some(Version::V2),
+            method: method.to_string(),
+            params,
+            id: None,
+        }));
+        Ok(())
+    }
+
+    /// Returns the number of queued requests and notifications.
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Returns true if nothing is queued.
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
     }
-    pub fn handle_response(&mut self, response: Response) {
-        if let Some(id) = &response.id {
-            let tx = self.pending_requests.remove(id);
-            if let Some(tx) = tx {
-                let _ = tx.send(response);
+
+    /// Sends all queued requests and notifications as a single batch.
+    ///
+    /// Empty batches are not sent.
+    /// If sending fails, the requests fail with the same error.
+    pub async fn send(mut self) -> Result<(), Error> {
+        if self.messages.is_empty() {
+            return Ok(());
+        }
+        let messages = std::mem::take(&mut self.messages);
+        self.client.inner.lock().unwrap().insert_batch(&self.ids);
+        if let Err(err) = self.client.tx(Message::Batch(messages)).await {
+            let mut inner = self.client.inner.lock().unwrap();
+            for id in self.ids.drain(..) {
+                if let Some(tx) = inner.remove(&id) {
+                    let error = Error::with_data(err.code, err.message.clone(), err.data.clone());
+                    let _ = tx.send((Response::error(Some(id), error), 0));
+                }
             }
+            return Err(err);
         }
+        self.sent.store(true, Ordering::SeqCst);
+        self.ids.clear();
+        Ok(())
+    }
+}
+
+impl Drop for RpcBatch {
+    fn drop(&mut self) {
+        // Requests of a batch that was never sent.
+        if let Ok(mut inner) = self.client.inner.lock() {
+            for id in &self.ids {
+                inner.remove(id);
+            }
+        }
+    }
+}
+
+/// Future resolving to the result of a request sent in a batch.
+///
+/// Like [`ResponseFuture`], dropping it before the response arrives
+/// removes the pending request and cancels it if the batch was sent.
+pub struct BatchResponse {
+    response: ResponseFuture,
+    /// Default timeout of the client.
+    timeout: Option<Duration>,
+    /// Timer started on the first poll.
+    delay: Option<Delay>,
+}
+
+impl BatchResponse {
+    /// Returns the ID of the request.
+    pub fn id(&self) -> &Id {
+        self.response.id()
+    }
+}
+
+impl Future for BatchResponse {
+    type Output = Result<serde_json::Value, Error>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        if let Poll::Ready(res) = Pin::new(&mut this.response).poll(cx) {
+            return Poll::Ready(res);
+        }
+        let Some(timeout) = this.timeout else {
+            return Poll::Pending;
+        };
+        let delay = this.delay.get_or_insert_with(|| Delay::new(timeout));
+        Pin::new(delay).poll(cx).map(|()| Err(Error::timeout()))
+    }
+}
+
+/// Serializes a response.
+///
+/// A response that fails to serialize is replaced by an [`Error::INTERNAL_ERROR`]
+/// response with the same ID, so the remote side does not wait for it forever.
+/// Subscriptions started by the request are dropped then.
+fn encode_response(response: RawResponse, pending: &PendingSubscriptions) -> Option<String> {
+    encode(&response)
+        .or_else(|err| {
+            log::error!("Failed to serialize JSON-RPC response: {}", err.message);
+            pending
+                .lock()
+                .unwrap()
+                .retain(|(id, _, _)| response.id.as_ref() != Some(id));
+            encode(&RawResponse::error(response.id, err))
+        })
+        .ok()
+}
+
+/// Waits for a future to complete at most for the given duration.
+async fn with_timeout<T>(
+    fut: impl Future<Output = Result<T, Error>>,
+    timeout: Duration,
+) -> Result<T, Error> {
+    let fut = std::pin::pin!(fut);
+    match select(fut, Delay::new(timeout)).await {
+        Either::Left((res, _)) => res,
+        Either::Right(_) => Err(Error::timeout()),
+    }
+}
+
+/// Creates a `$/cancelRequest` notification for the given request.
+fn cancel_notification(id: Id) -> Message {
+    let params = downcast_params(Some(CancelParams { id })).unwrap_or_default();
+    Message::Request(Request {
+        jsonrpc: Some(Version::V2),
+        method: CANCEL_REQUEST.to_string(),
+        params,
+        id: None,
+    })
+}
+
+/// Decodes raw params, `None` as `null`.
+fn decode_params<T: DeserializeOwned>(params: &RawParams) -> serde_json::Result<T> {
+    serde_json::from_str(params.as_deref().map_or("null", RawValue::get))
+}
+
+fn response_into_result(res: Response) -> Result<serde_json::Value, Error> {
+    match (res.result, res.error) {
+        (Some(result), None) => Ok(result),
+        (None, Some(error)) => Err(error),
+        // TODO: better error.
+        _ => Err(Error::bad_response()),
     }
 }
 
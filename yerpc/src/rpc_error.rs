@@ -0,0 +1,44 @@
+//! Typed error enums returned by RPC methods.
+//!
+//! Deriving [`RpcError`](macro@crate::RpcError) for an enum maps each variant
+//! to its own error code and message and puts the variant fields in `data`:
+//!
+//! ```
+//! #[derive(yerpc::RpcError)]
+//! enum AccountError {
+//!     #[rpc_error(code = 1000, message = "Account not found")]
+//!     NotFound,
+//!     #[rpc_error(code = 1001, message = "Quota exceeded")]
+//!     QuotaExceeded { used: u64, limit: u64 },
+//! }
+//! ```
+//!
+//! Methods of an `#[rpc]` server can then return `Result<T, AccountError>`.
+//! Methods marked with `#[rpc(typed_error)]` export the possible errors
+//! in the generated TypeScript and OpenRPC definitions.
+use crate::{Error, TypeDef};
+
+/// Error enum with a fixed set of error codes.
+///
+/// Implemented by `#[derive(RpcError)]` together with [`TypeDef`],
+/// which describes the union of the possible error objects.
+pub trait RpcError: Into<Error> + TypeDef {
+    /// Name of the error type in type definitions.
+    const NAME: &'static str;
+
+    /// Descriptions of all variants.
+    const VARIANTS: &'static [ErrorVariant];
+}
+
+/// Description of an error enum variant.
+#[derive(Debug)]
+pub struct ErrorVariant {
+    /// Name of the variant.
+    pub name: &'static str,
+
+    /// Error code.
+    pub code: i32,
+
+    /// Error message.
+    pub message: &'static str,
+}
@@ -0,0 +1,83 @@
+//! Tracing spans of incoming and outgoing calls.
+//!
+//! With the `tracing` feature every request and notification processed by an
+//! [`RpcSession`](crate::RpcSession) and every request sent by an
+//! [`RpcClient`](crate::RpcClient) runs inside an `rpc` span,
+//! so events emitted by handlers are tied to the call that caused them.
+//!
+//! The spans have the fields `rpc.method`, `rpc.id`, `rpc.direction`
+//! (`incoming` or `outgoing`), `rpc.params_size` (size of the serialized params in bytes),
+//! `rpc.outcome` (`ok` or `error`) and `rpc.error_code`.
+use serde::Serialize;
+use tracing::{field, Span};
+
+use crate::{Error, Id, RawParams};
+
+/// Creates the span of an incoming request or notification.
+pub(crate) fn incoming(method: &str, id: Option<&Id>, params: &RawParams) -> Span {
+    let span = tracing::info_span!(
+        "rpc",
+        rpc.method = method,
+        rpc.id = field::Empty,
+        rpc.direction = "incoming",
+        rpc.params_size = field::Empty,
+        rpc.outcome = field::Empty,
+        rpc.error_code = field::Empty,
+    );
+    if let Some(id) = id {
+        record_id(&span, id);
+    }
+    let params_size = params.as_ref().map_or(0, |params| params.get().len());
+    span.record("rpc.params_size", params_size);
+    span
+}
+
+/// Creates the span of an outgoing request.
+///
+/// The request ID and params size are recorded when the request is sent.
+pub(crate) fn outgoing(method: &str) -> Span {
+    tracing::info_span!(
+        "rpc",
+        rpc.method = method,
+        rpc.id = field::Empty,
+        rpc.direction = "outgoing",
+        rpc.params_size = field::Empty,
+        rpc.outcome = field::Empty,
+        rpc.error_code = field::Empty,
+    )
+}
+
+/// Records the ID of the call in `span`.
+pub(crate) fn record_id(span: &Span, id: &Id) {
+    match id {
+        Id::Number(id) => span.record("rpc.id", id),
+        Id::String(id) => span.record("rpc.id", id.as_str()),
+        Id::Null => span.record("rpc.id", "null"),
+    };
+}
+
+/// Records the size of the serialized params in `span`.
+///
+/// The params are only serialized if the span is enabled.
+pub(crate) fn record_params_size(span: &Span, params: &impl Serialize) {
+    if span.is_disabled() {
+        return;
+    }
+    if let Ok(params) = serde_json::to_vec(params) {
+        span.record("rpc.params_size", params.len());
+    }
+}
+
+/// Records the outcome of the call in the current span.
+pub(crate) fn record_outcome(error: Option<&Error>) {
+    let span = Span::current();
+    match error {
+        Some(error) => {
+            span.record("rpc.outcome", "error");
+            span.record("rpc.error_code", error.code);
+        }
+        None => {
+            span.record("rpc.outcome", "ok");
+        }
+    }
+}
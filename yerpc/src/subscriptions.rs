@@ -0,0 +1,173 @@
+//! Subscriptions streaming items from the server to the client.
+//!
+//! A subscription is started with a request to a method marked with
+//! `#[rpc(subscription)]`, which returns the subscription ID.
+//! Each item of the stream is then sent as a `$/subscription` notification
+//! and the end of the stream as a `$/subscriptionEnd` notification,
+//! carrying an error if an item could not be serialized.
+//! The client can stop the stream early with a `$/unsubscribe` request.
+//! A subscription starts once the response carrying its ID is queued.
+//! Its items are forwarded by the future returned by
+//! [`OutReceiver::forward_subscriptions`](crate::OutReceiver::forward_subscriptions),
+//! and subscriptions stop when the outgoing queue is closed.
+use futures::future::{AbortHandle, Abortable, BoxFuture};
+use futures::stream::{BoxStream, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::{Error, RpcClient, TypeDef};
+
+/// Method name of the notification carrying a subscription item.
+pub const SUBSCRIPTION: &str = "$/subscription";
+
+/// Method name of the notification sent when a subscription ends.
+pub const SUBSCRIPTION_END: &str = "$/subscriptionEnd";
+
+/// Method name of the request stopping a subscription.
+pub const UNSUBSCRIBE: &str = "$/unsubscribe";
+
+/// Subscription identifier, unique within a session.
+pub type SubscriptionId = i64;
+
+/// Stream of serialized subscription items,
+/// or of the error serializing an item.
+pub type SubscriptionStream = BoxStream<'static, Result<serde_json::Value, Error>>;
+
+/// Params of the `$/subscription` notification.
+#[derive(Serialize, Deserialize, Debug, TypeDef)]
+pub struct SubscriptionItem {
+    /// ID of the subscription.
+    pub subscription: SubscriptionId,
+
+    /// Item of the stream.
+    pub item: serde_json::Value,
+}
+
+/// Params of the `$/unsubscribe` request.
+#[derive(Serialize, Deserialize, Debug, TypeDef)]
+pub struct SubscriptionParams {
+    /// ID of the subscription.
+    pub subscription: SubscriptionId,
+}
+
+/// Params of the `$/subscriptionEnd` notification.
+#[derive(Serialize, Deserialize, Debug, TypeDef)]
+pub struct SubscriptionEnd {
+    /// ID of the subscription.
+    pub subscription: SubscriptionId,
+
+    /// Error that ended the subscription, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<Error>,
+}
+
+/// Converts the stream returned by a subscription method
+/// into a stream of JSON values.
+///
+/// Used by the code generated with the [`rpc`](crate::rpc) macro.
+/// An item that fails to serialize ends the subscription with the error.
+pub fn subscription_stream<S>(stream: S) -> SubscriptionStream
+where
+    S: Stream + Send + 'static,
+    S::Item: Serialize,
+{
+    stream
+        .map(|item| serde_json::to_value(item).map_err(|err| Error::internal(err.to_string())))
+        .boxed()
+}
+
+/// Active subscriptions of a session.
+#[derive(Default)]
+pub(crate) struct Subscriptions {
+    next_id: SubscriptionId,
+    active: HashMap<SubscriptionId, AbortHandle>,
+}
+
+impl Subscriptions {
+    /// Allocates the ID of a new subscription.
+    ///
+    /// The subscription is only registered by [`Subscriptions::start`],
+    /// once the response carrying the ID is queued.
+    pub(crate) fn next_id(&mut self) -> SubscriptionId {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// Registers the subscription `id` and returns the task forwarding its items
+    /// as notifications through the client.
+    pub(crate) fn start(
+        this: &Arc<Mutex<Self>>,
+        id: SubscriptionId,
+        stream: SubscriptionStream,
+        client: RpcClient,
+    ) -> BoxFuture<'static, ()> {
+        let (handle, registration) = AbortHandle::new_pair();
+        this.lock().unwrap().active.insert(id, handle);
+        let active = Active {
+            subscriptions: this.clone(),
+            id,
+        };
+        let task = async move {
+            let forward = async {
+                let mut stream = stream;
+                while let Some(item) = stream.next().await {
+                    let item = SubscriptionItem {
+                        subscription: id,
+                        item: item?,
+                    };
+                    client.send_notification(SUBSCRIPTION, Some(item)).await?;
+                }
+                Ok::<_, Error>(())
+            };
+            let error = match Abortable::new(forward, registration).await {
+                Ok(Err(err)) if !err.is_disconnnected() => Some(err),
+                _ => None,
+            };
+            drop(active);
+            let params = SubscriptionEnd {
+                subscription: id,
+                error,
+            };
+            let _ = client
+                .send_notification(SUBSCRIPTION_END, Some(params))
+                .await;
+        };
+        Box::pin(task)
+    }
+
+    /// Stops a subscription.
+    ///
+    /// Returns false if there is no such subscription.
+    pub(crate) fn stop(&mut self, id: SubscriptionId) -> bool {
+        match self.active.remove(&id) {
+            Some(handle) => {
+                handle.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stops all subscriptions.
+    pub(crate) fn stop_all(&mut self) {
+        for (_, handle) in self.active.drain() {
+            handle.abort();
+        }
+    }
+}
+
+/// Unregisters a subscription when dropped,
+/// also if its task is dropped with the receiver.
+struct Active {
+    subscriptions: Arc<Mutex<Subscriptions>>,
+    id: SubscriptionId,
+}
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        if let Ok(mut subscriptions) = self.subscriptions.lock() {
+            subscriptions.active.remove(&self.id);
+        }
+    }
+}
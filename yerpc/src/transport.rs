@@ -0,0 +1,247 @@
+//! Driver running a session over any message-based transport.
+//!
+//! [`serve`] and [`serve_frames`] read incoming messages from a [`Stream`],
+//! dispatch them to the session and write outgoing messages to a [`Sink`]
+//! until either side ends. The integrations are thin adapters around them,
+//! so new transports only have to map their messages to and from frames.
+//! Messages that a transport cannot read are passed on as [`Received::Invalid`]
+//! and answered by the driver.
+use futures::future::{poll_fn, ready};
+use futures::stream::FuturesUnordered;
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use std::fmt;
+use std::task::Poll;
+
+use crate::codec::{Codec, Json};
+use crate::{Error, OutReceiver, RpcServer, RpcSession};
+
+/// Message of a transport.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    /// UTF-8 text, always JSON.
+    Text(String),
+
+    /// Binary message encoded with the codec of the connection.
+    Binary(Vec<u8>),
+}
+
+/// Item read from a transport.
+///
+/// Streams passed to [`serve`] and [`serve_frames`] may yield anything
+/// convertible into it, such as a `String` or a [`Frame`].
+#[derive(Debug)]
+pub enum Received {
+    /// Message to process.
+    Frame(Frame),
+
+    /// Message that could not be read, such as an overlong line.
+    ///
+    /// It is skipped and answered with the error.
+    /// The response has a `null` id, the id of an unreadable message is unknown.
+    Invalid(Error),
+}
+
+impl From<Frame> for Received {
+    fn from(frame: Frame) -> Self {
+        Received::Frame(frame)
+    }
+}
+
+impl From<String> for Received {
+    fn from(message: String) -> Self {
+        Received::Frame(Frame::Text(message))
+    }
+}
+
+impl From<Error> for Received {
+    fn from(error: Error) -> Self {
+        Received::Invalid(error)
+    }
+}
+
+/// Error stopping a transport.
+#[derive(Debug)]
+pub enum TransportError<R, S> {
+    /// Reading an incoming message failed.
+    Recv(R),
+
+    /// Writing an outgoing message failed.
+    Send(S),
+
+    /// A message could not be encoded, or a binary message arrived
+    /// while the codec of the connection is not binary.
+    Codec(Error),
+}
+
+impl<R: fmt::Display, S: fmt::Display> fmt::Display for TransportError<R, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportError::Recv(err) => write!(f, "Failed to receive message: {err}"),
+            TransportError::Send(err) => write!(f, "Failed to send message: {err}"),
+            TransportError::Codec(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl<R, S> std::error::Error for TransportError<R, S>
+where
+    R: std::error::Error,
+    S: std::error::Error,
+{
+}
+
+/// Serves `session` over a transport of JSON text messages.
+///
+/// Runs until `incoming` ends or fails, or the outgoing queue is closed.
+/// Once `incoming` ends, requests in flight are finished
+/// and their responses sent before `outgoing` is closed.
+/// Active subscriptions are stopped then, each one ending with
+/// a `$/subscriptionEnd` notification.
+/// Incoming messages are dispatched concurrently if
+/// [`RpcSession::with_max_concurrent_requests`] is set,
+/// otherwise one after another, while outgoing messages keep flowing.
+/// Items of `incoming` are messages or [`Received::Invalid`] errors to answer.
+pub async fn serve<T, R, W, I, E>(
+    session: RpcSession<T>,
+    out_rx: OutReceiver,
+    incoming: R,
+    outgoing: W,
+) -> Result<(), TransportError<E, W::Error>>
+where
+    T: RpcServer,
+    R: Stream<Item = Result<I, E>>,
+    I: Into<Received>,
+    W: Sink<String>,
+{
+    // JSON is only ever sent as text.
+    let outgoing = outgoing.with(|frame| {
+        ready(Ok(match frame {
+            Frame::Text(message) => message,
+            Frame::Binary(message) => String::from_utf8_lossy(&message).into_owned(),
+        }))
+    });
+    serve_frames(session, out_rx, &Json, incoming, outgoing).await
+}
+
+/// Serves `session` over a transport of text and binary messages,
+/// such as a WebSocket, like [`serve`].
+///
+/// Outgoing messages are sent in binary frames if `codec` is binary.
+/// Incoming text frames are always decoded as JSON.
+pub async fn serve_frames<T, R, W, I, E>(
+    session: RpcSession<T>,
+    out_rx: OutReceiver,
+    codec: &dyn Codec,
+    incoming: R,
+    outgoing: W,
+) -> Result<(), TransportError<E, W::Error>>
+where
+    T: RpcServer,
+    R: Stream<Item = Result<I, E>>,
+    I: Into<Received>,
+    W: Sink<Frame>,
+{
+    enum Event<F> {
+        Outgoing(Option<String>),
+        Incoming(Option<F>),
+        Finished,
+    }
+
+    let session = &session;
+    // With concurrent dispatch, messages are always read, so that responses
+    // and cancellations reach requests in flight; the session rejects
+    // requests over the limit.
+    let concurrent = session.max_concurrent_requests().is_some();
+    let mut in_flight = FuturesUnordered::new();
+    // Subscription items are forwarded apart from writing outgoing messages.
+    let mut forwarder = out_rx.forwarder();
+    let mut out_rx = out_rx;
+    let mut reading = true;
+    futures::pin_mut!(incoming, outgoing);
+    loop {
+        let done = !reading && in_flight.is_empty();
+        if done {
+            // Also stops subscriptions started by the last requests.
+            session.stop_subscriptions();
+        }
+        if done && forwarder.is_idle() {
+            // Send what the finished requests and subscriptions queued.
+            while let Ok(message) = out_rx.try_recv() {
+                let frame = encode(codec, message)?;
+                outgoing.feed(frame).await.map_err(TransportError::Send)?;
+            }
+            break;
+        }
+        let event = poll_fn(|cx| {
+            if let Poll::Ready(Some(())) = in_flight.poll_next_unpin(cx) {
+                return Poll::Ready(Event::Finished);
+            }
+            // Ready once the queue is closed, which also ends the outgoing messages.
+            let _ = forwarder.poll(cx);
+            if let Poll::Ready(message) = out_rx.poll_next_unpin(cx) {
+                return Poll::Ready(Event::Outgoing(message));
+            }
+            // Stopped subscriptions may end without queuing anything.
+            if done && forwarder.is_idle() {
+                return Poll::Ready(Event::Finished);
+            }
+            // Without concurrent dispatch, the next message waits for the current one.
+            if reading && (concurrent || in_flight.is_empty()) {
+                if let Poll::Ready(frame) = incoming.as_mut().poll_next(cx) {
+                    return Poll::Ready(Event::Incoming(frame));
+                }
+            }
+            Poll::Pending
+        })
+        .await;
+        match event {
+            Event::Outgoing(Some(message)) => {
+                let frame = encode(codec, message)?;
+                outgoing.send(frame).await.map_err(TransportError::Send)?;
+            }
+            // The queue was closed to disconnect a slow peer.
+            Event::Outgoing(None) => break,
+            Event::Incoming(Some(Ok(received))) => {
+                let frame = match received.into() {
+                    Received::Frame(frame) => frame,
+                    Received::Invalid(error) => {
+                        session.client().send_error(error).await;
+                        continue;
+                    }
+                };
+                let (codec, frame): (&dyn Codec, _) = match frame {
+                    Frame::Text(message) => {
+                        #[cfg(feature = "tracing")]
+                        tracing::trace!("RPC recv {}", message);
+                        (&Json, message.into_bytes())
+                    }
+                    Frame::Binary(frame) if codec.is_binary() => (codec, frame),
+                    Frame::Binary(_) => {
+                        return Err(TransportError::Codec(Error::new(
+                            Error::PARSE_ERROR,
+                            "Binary messages are not supported.".to_string(),
+                        )))
+                    }
+                };
+                in_flight.push(session.handle_incoming_frame(codec, frame));
+            }
+            Event::Incoming(Some(Err(err))) => return Err(TransportError::Recv(err)),
+            Event::Incoming(None) => reading = false,
+            Event::Finished => {}
+        }
+    }
+    outgoing.close().await.map_err(TransportError::Send)
+}
+
+/// Encodes an outgoing message into a frame.
+fn encode<R, S>(codec: &dyn Codec, message: String) -> Result<Frame, TransportError<R, S>> {
+    #[cfg(feature = "tracing")]
+    tracing::trace!("RPC send {}", message);
+    match codec.is_binary() {
+        true => codec
+            .encode(message)
+            .map(Frame::Binary)
+            .map_err(TransportError::Codec),
+        false => Ok(Frame::Text(message)),
+    }
+}
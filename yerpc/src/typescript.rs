@@ -3,6 +3,8 @@ use std::io;
 use std::path::Path;
 use typescript_type_def::{type_expr::TypeInfo, write_definition_file, DefinitionFileOptions};
 
+use crate::RpcError;
+
 pub use typescript_type_def as type_def;
 pub use typescript_type_def::TypeDef;
 
@@ -28,6 +30,13 @@ pub fn export_types_to_file<T: TypeDef>(
 pub struct Method {
     pub is_notification: bool,
     pub is_positional: bool,
+    /// Returns an `AsyncIterable` of subscription items instead of a `Promise`.
+    pub is_subscription: bool,
+    /// Type of the progress values passed to the `onProgress` callback,
+    /// if the method reports progress.
+    pub progress: Option<&'static TypeInfo>,
+    /// Name of the typed error enum the method fails with, if any.
+    pub error: Option<&'static str>,
     pub ts_name: String,
     pub rpc_name: String,
     pub args: Vec<(String, &'static TypeInfo)>,
@@ -52,13 +61,16 @@ impl Method {
             output,
             is_notification,
             is_positional,
+            is_subscription: false,
+            progress: None,
+            error: None,
             docs: docs.map(|d| d.to_string()),
         }
     }
 
     pub fn to_string(&self, root_namespace: Option<&str>) -> String {
         let (args, call) = if !self.is_positional {
-            if let Some((name, ty)) = self.args.get(0) {
+            if let Some((name, ty)) = self.args.first() {
                 (
                     format!("{}: {}", name, type_to_expr(ty, root_namespace)),
                     name.to_string(),
@@ -83,17 +95,56 @@ impl Method {
             );
             (args, call)
         };
+        // Progress is passed to a callback after all other arguments.
+        let (args, call) = match self.progress {
+            Some(progress) => {
+                let callback = format!(
+                    "onProgress?: (progress: {}) => void",
+                    type_to_expr(progress, root_namespace)
+                );
+                let args = if args.is_empty() {
+                    callback
+                } else {
+                    format!("{args}, {callback}")
+                };
+                (
+                    args,
+                    format!("{call} as RPC.Params, onProgress as ProgressCallback"),
+                )
+            }
+            None => (args, format!("{call} as RPC.Params")),
+        };
         let output = self.output.map_or_else(
             || "void".to_string(),
             |output| type_to_expr(output, root_namespace),
         );
-        let (output, inner_method) = if !self.is_notification {
+        let (output, inner_method) = if self.is_subscription {
+            (format!("AsyncIterable<{output}>"), "subscribe")
+        } else if !self.is_notification {
             (format!("Promise<{output}>"), "request")
         } else {
             (output, "notification")
         };
-        let docs = if let Some(docs) = &self.docs {
-            let docs = docs.split('\n').fold(String::new(), |mut output, s| {
+        // Transports may not implement the optional `subscribe` method.
+        let check = if self.is_subscription {
+            "    if (!this._transport.subscribe) {\n      throw new Error('Transport does not support subscriptions');\n    }\n"
+        } else {
+            ""
+        };
+        let mut lines: Vec<String> = self
+            .docs
+            .iter()
+            .flat_map(|docs| docs.split('\n'))
+            .map(ToString::to_string)
+            .collect();
+        if let Some(error) = self.error {
+            lines.push(format!(
+                " @throws {{{}}}",
+                type_name_expr(error, root_namespace)
+            ));
+        }
+        let docs = if !lines.is_empty() {
+            let docs = lines.iter().fold(String::new(), |mut output, s| {
                 let _ = writeln!(output, "   *{s}");
                 output
             });
@@ -102,12 +153,30 @@ impl Method {
             "".into()
         };
         format!(
-            "{}\n  public {}({}): {} {{\n    return (this._transport.{}('{}', {} as RPC.Params)) as {};\n  }}\n\n",
-            docs, self.ts_name, args, output, inner_method, self.rpc_name, call, output
+            "{}\n  public {}({}): {} {{\n{}    return (this._transport.{}('{}', {})) as {};\n  }}\n\n",
+            docs, self.ts_name, args, output, check, inner_method, self.rpc_name, call, output
         )
     }
 }
 
+fn type_name_expr(name: &str, root_namespace: Option<&str>) -> String {
+    match root_namespace {
+        Some(namespace) => format!("{namespace}.{name}"),
+        None => name.to_string(),
+    }
+}
+
+/// Returns the TypeScript constants with the codes of an error enum.
+pub fn error_codes_to_string<E: RpcError>() -> String {
+    let codes = E::VARIANTS
+        .iter()
+        .fold(String::new(), |mut output, variant| {
+            let _ = writeln!(output, "  {}: {},", variant.name, variant.code);
+            output
+        });
+    format!("export const {} = {{\n{codes}}} as const;\n", E::NAME)
+}
+
 fn type_to_expr(ty: &'static TypeInfo, root_namespace: Option<&str>) -> String {
     let mut expr = vec![];
     ty.write_ref_expr(&mut expr, root_namespace).unwrap();
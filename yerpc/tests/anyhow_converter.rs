@@ -0,0 +1,111 @@
+#![cfg(feature = "anyhow")]
+
+mod common;
+
+use anyhow::Context;
+use common::{notification, request};
+use std::sync::{Arc, Mutex};
+use yerpc::{rpc, AnyhowConverter, AnyhowMessage, Error, RpcSession, SessionError};
+
+struct Api;
+
+#[rpc(all_positional, ts_outdir = "../target/test-bindings/anyhow")]
+impl Api {
+    pub async fn open(&self) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!("Permission denied")).context("Failed to open database")?;
+        Ok(())
+    }
+
+    #[rpc(notification)]
+    pub async fn close(&self) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!("Permission denied")).context("Failed to close database")?;
+        Ok(())
+    }
+}
+
+/// Returns the error of calling `method` through `converter`.
+async fn error(converter: AnyhowConverter, method: &str) -> Error {
+    let (session, _out_rx) = RpcSession::create(Api);
+    let session = session.with_anyhow_converter(converter);
+    let res = session
+        .process_incoming(&request(1, method, ()))
+        .await
+        .unwrap();
+    match res.parse() {
+        Ok(yerpc::Message::Response(response)) => response.error.unwrap(),
+        res => panic!("Unexpected response {res:?}"),
+    }
+}
+
+#[tokio::test]
+async fn anyhow_converter() -> anyhow::Result<()> {
+    let err = error(AnyhowConverter::new(AnyhowMessage::Hidden), "open").await;
+    assert_eq!(err.code, Error::INTERNAL_ERROR);
+    assert_eq!(err.message, "Internal server error");
+    assert_eq!(err.data, None);
+
+    let err = error(
+        AnyhowConverter::new(AnyhowMessage::TopLevel).with_chain(),
+        "open",
+    )
+    .await;
+    assert_eq!(err.code, -1);
+    assert_eq!(err.message, "Failed to open database");
+    assert_eq!(
+        err.data,
+        Some(serde_json::json!({
+            "chain": ["Failed to open database", "Permission denied"]
+        }))
+    );
+
+    let err = error(
+        AnyhowConverter::new(AnyhowMessage::Hidden).with_correlation_id(),
+        "open",
+    )
+    .await;
+    assert_eq!(err.message, "Internal server error");
+    let data = err.data.unwrap();
+    assert!(data["correlationId"]
+        .as_str()
+        .is_some_and(|id| !id.is_empty()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn unconverted_errors() -> anyhow::Result<()> {
+    // Errors not converted from anyhow are passed through.
+    let (session, _out_rx) = common::session();
+    let session =
+        session.with_anyhow_converter(AnyhowConverter::new(AnyhowMessage::Hidden).with_chain());
+    let res = session.process_incoming(&request(1, "fail", ())).await;
+    assert_eq!(res.unwrap(), common::error(1, 1, "failed"));
+
+    // Errors are plain structs, also with the `anyhow` feature.
+    let error = Error {
+        code: 1,
+        message: "failed".to_string(),
+        data: None,
+    };
+    assert_eq!(error.to_string(), "JSON-RPC error: failed (code 1)");
+    Ok(())
+}
+
+#[tokio::test]
+async fn notification_errors() -> anyhow::Result<()> {
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let (session, _out_rx) = RpcSession::create(Api);
+    let session = session
+        .with_anyhow_converter(AnyhowConverter::new(AnyhowMessage::TopLevel))
+        .with_error_hook({
+            let errors = errors.clone();
+            move |err| errors.lock().unwrap().push(err)
+        });
+    let res = session.process_incoming(&notification("close", ())).await;
+    assert!(res.is_none());
+    let errors = errors.lock().unwrap();
+    assert!(matches!(
+        &errors[..],
+        [SessionError::Notification { error, .. }] if error.message == "Failed to close database"
+    ));
+    Ok(())
+}
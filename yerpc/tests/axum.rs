@@ -5,6 +5,7 @@ mod tests {
     use std::net::SocketAddr;
     use tokio::net::TcpStream;
     use tokio_tungstenite::client_async;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
     use tokio_tungstenite::tungstenite::http::StatusCode;
     use tokio_tungstenite::tungstenite::Message;
     use yerpc::axum::handle_ws_rpc;
@@ -19,7 +20,7 @@ mod tests {
         }
     }
 
-    #[rpc(all_positional, ts_outdir = "typescript/generated")]
+    #[rpc(all_positional, ts_outdir = "../target/test-bindings/axum")]
     impl Api {
         async fn shout(&self, msg: String) -> String {
             msg.to_uppercase()
@@ -29,6 +30,57 @@ mod tests {
         }
     }
 
+    /// Answers `wait` once `release` is notified.
+    struct Gate {
+        tx: async_channel::Sender<()>,
+        rx: async_channel::Receiver<()>,
+    }
+
+    mod gate {
+        use yerpc::rpc;
+
+        #[rpc(all_positional, ts_outdir = "../target/test-bindings/axum_gate")]
+        impl super::Gate {
+            async fn wait(&self) -> bool {
+                self.rx.recv().await.is_ok()
+            }
+
+            #[rpc(notification)]
+            async fn release(&self) {
+                let _ = self.tx.send(()).await;
+            }
+        }
+    }
+
+    /// Returns what it knows about the connection of the peer.
+    struct Peer;
+
+    mod peer {
+        use yerpc::axum::ConnectionInfo;
+        use yerpc::{rpc, Meta};
+
+        #[rpc(all_positional, ts_outdir = "../target/test-bindings/axum_peer")]
+        impl super::Peer {
+            async fn peer(
+                &self,
+                #[rpc(context)] Meta(info): Meta<ConnectionInfo>,
+            ) -> (bool, Option<String>) {
+                let agent = info.headers.get("user-agent");
+                let agent = agent
+                    .and_then(|agent| agent.to_str().ok())
+                    .map(String::from);
+                (info.peer_addr.is_some(), agent)
+            }
+        }
+    }
+
+    /// Binds a listener to a free port.
+    async fn listen() -> (tokio::net::TcpListener, SocketAddr) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        (listener, addr)
+    }
+
     async fn handler(ws: WebSocketUpgrade) -> Response {
         let (client, out_receiver) = RpcClient::new();
         let api = Api::new();
@@ -39,16 +91,13 @@ mod tests {
     #[tokio::test]
     async fn test_axum_websocket() -> anyhow::Result<()> {
         let app = Router::new().route("/rpc", get(handler));
-        let addr = SocketAddr::from(([127, 0, 0, 1], 12345));
-        let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+        let (listener, addr) = listen().await;
         tokio::spawn(async move {
             axum::serve(listener, app).await.unwrap();
         });
 
-        let tcp = TcpStream::connect("127.0.0.1:12345")
-            .await
-            .expect("Failed to connect");
-        let url = url::Url::parse("ws://localhost:12345/rpc").unwrap();
+        let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+        let url = url::Url::parse(&format!("ws://{addr}/rpc")).unwrap();
         let (mut stream, response) = client_async(url, tcp)
             .await
             .expect("Client failed to connect");
@@ -76,4 +125,173 @@ mod tests {
         assert_eq!(res.as_str(), "FOO");
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_concurrent_dispatch() -> anyhow::Result<()> {
+        async fn handler(ws: WebSocketUpgrade) -> Response {
+            let (client, out_receiver) = RpcClient::new();
+            let (tx, rx) = async_channel::bounded(1);
+            let session = RpcSession::new(client, Gate { tx, rx }).with_max_concurrent_requests(4);
+            handle_ws_rpc(ws, out_receiver, session).await
+        }
+
+        let app = Router::new().route("/rpc", get(handler));
+        let (listener, addr) = listen().await;
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+        let url = url::Url::parse(&format!("ws://{addr}/rpc")).unwrap();
+        let (stream, _response) = client_async(url, tcp)
+            .await
+            .expect("Client failed to connect");
+
+        // The request only finishes once the notification
+        // sent after it has been processed.
+        let (client, _on_close) = tungstenite_client(stream, ());
+        let wait = tokio::spawn({
+            let client = client.clone();
+            async move { client.send_request("wait", None::<()>).await }
+        });
+        tokio::task::yield_now().await;
+        client.send_notification("release", None::<()>).await?;
+        let res = wait.await??;
+        assert_eq!(res, true);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_connection_info() -> anyhow::Result<()> {
+        use yerpc::axum::ConnectionInfo;
+
+        async fn handler(ws: WebSocketUpgrade, info: ConnectionInfo) -> Response {
+            let (client, out_receiver) = RpcClient::new();
+            let session = RpcSession::new(client, Peer).with_metadata(info);
+            handle_ws_rpc(ws, out_receiver, session).await
+        }
+
+        let app = Router::new().route("/rpc", get(handler));
+        let (listener, addr) = listen().await;
+        tokio::spawn(async move {
+            let app = app.into_make_service_with_connect_info::<SocketAddr>();
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+        let mut request = format!("ws://{addr}/rpc").into_client_request()?;
+        request
+            .headers_mut()
+            .insert("user-agent", "yerpc-test".parse()?);
+        let (stream, _response) = client_async(request, tcp)
+            .await
+            .expect("Client failed to connect");
+
+        let (client, _on_close) = tungstenite_client(stream, ());
+        let res = client.send_request("peer", None::<()>).await?;
+        assert_eq!(res, serde_json::json!([true, "yerpc-test"]));
+        Ok(())
+    }
+
+    /// JSON in binary frames, offered next to plain JSON.
+    struct Binary;
+
+    impl yerpc::codec::Codec for Binary {
+        fn subprotocol(&self) -> &'static str {
+            "jsonrpc.binary"
+        }
+
+        fn is_binary(&self) -> bool {
+            true
+        }
+
+        fn encode(&self, message: String) -> Result<Vec<u8>, yerpc::Error> {
+            Ok(message.into_bytes())
+        }
+
+        fn decode(&self, frame: Vec<u8>) -> Result<String, yerpc::Error> {
+            String::from_utf8(frame)
+                .map_err(|err| yerpc::Error::new(yerpc::Error::PARSE_ERROR, err.to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_binary_codec() -> anyhow::Result<()> {
+        use std::sync::Arc;
+        use yerpc::codec::{self, Codec};
+        use yerpc::tungstenite::{negotiated_codec, tungstenite_client_with_codec};
+
+        async fn handler(ws: WebSocketUpgrade) -> Response {
+            let (session, out_receiver) = RpcSession::builder(Api::new()).codec(Binary).build();
+            handle_ws_rpc(ws, out_receiver, session).await
+        }
+
+        let app = Router::new().route("/rpc", get(handler));
+        let (listener, addr) = listen().await;
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+        let preferred: Arc<dyn Codec> = Arc::new(Binary);
+        let mut request = format!("ws://{addr}/rpc").into_client_request()?;
+        request
+            .headers_mut()
+            .insert("sec-websocket-protocol", codec::offer(&preferred).parse()?);
+        let (mut stream, response) = client_async(request, tcp)
+            .await
+            .expect("Client failed to connect");
+        let codec = negotiated_codec(response.headers(), &preferred);
+        assert_eq!(codec.subprotocol(), "jsonrpc.binary");
+
+        let frame = br#"{"jsonrpc":"2.0","method":"shout","params":["foo"],"id":1}"#;
+        stream.send(Message::Binary(frame.to_vec())).await?;
+        let res = stream.next().await.unwrap()?;
+        match res {
+            Message::Binary(frame) => {
+                let text = codec.decode(frame)?;
+                assert_eq!(text, r#"{"jsonrpc":"2.0","id":1,"result":"FOO"}"#);
+            }
+            _ => panic!("Received unexepcted message {:?}", res),
+        }
+
+        let (client, _on_close) = tungstenite_client_with_codec(stream, (), codec);
+        let res = client.send_request("add", Some([1.5, 2.0])).await?;
+        assert_eq!(res, 3.5);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tungstenite_server_codec() -> anyhow::Result<()> {
+        use std::sync::Arc;
+        use yerpc::codec::Codec;
+        use yerpc::tungstenite::{
+            accept_with_codec, handle_tungstenite, negotiated_codec, tungstenite_client_with_codec,
+        };
+
+        let (listener, addr) = listen().await;
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let preferred: Arc<dyn Codec> = Arc::new(Binary);
+            let (stream, codec) = accept_with_codec(tcp, &preferred).await.unwrap();
+            let (session, out_receiver) = RpcSession::builder(Api::new()).codec(codec).build();
+            handle_tungstenite(stream, out_receiver, session).await
+        });
+
+        // The server prefers its codec over the order of the offer.
+        let tcp = TcpStream::connect(addr).await?;
+        let mut request = format!("ws://{addr}/rpc").into_client_request()?;
+        request
+            .headers_mut()
+            .insert("sec-websocket-protocol", "jsonrpc, jsonrpc.binary".parse()?);
+        let (stream, response) = client_async(request, tcp).await?;
+        let preferred: Arc<dyn Codec> = Arc::new(Binary);
+        let codec = negotiated_codec(response.headers(), &preferred);
+        assert_eq!(codec.subprotocol(), "jsonrpc.binary");
+
+        let (client, _on_close) = tungstenite_client_with_codec(stream, (), codec);
+        let res = client.send_request("shout", Some(["foo"])).await?;
+        assert_eq!(res, "FOO");
+        Ok(())
+    }
 }
@@ -1,39 +1,26 @@
+mod common;
+
+use common::{request, response, session};
 use futures_util::StreamExt;
 use yerpc::{rpc, RpcSession};
 
 #[tokio::test]
 async fn basic() -> anyhow::Result<()> {
-    struct Api {}
-
-    #[rpc(all_positional, ts_outdir = "typescript/generated")]
-    impl Api {
-        pub async fn constant(&self) -> String {
-            "example".to_string()
-        }
-
-        pub async fn upper(&self, text: String) -> String {
-            text.to_uppercase()
-        }
-    }
-
-    let (session, mut out_rx) = RpcSession::create(Api {});
+    let (session, mut out_rx) = session();
 
     let req = r#"{"jsonrpc":"2.0","method":"constant","id":3}"#;
     session.handle_incoming(req).await;
     let out = out_rx.next().await.unwrap();
-    let out = serde_json::to_string(&out).unwrap();
     assert_eq!(out, r#"{"jsonrpc":"2.0","id":3,"result":"example"}"#);
 
     let req = r#"{"jsonrpc":"2.0","method":"upper","params":["foo"],"id":7}"#;
     session.handle_incoming(req).await;
     let out = out_rx.next().await.unwrap();
-    let out = serde_json::to_string(&out).unwrap();
     assert_eq!(out, r#"{"jsonrpc":"2.0","id":7,"result":"FOO"}"#);
 
     let client = session.client().clone();
     tokio::spawn(async move {
         let out = out_rx.next().await.unwrap();
-        let out = serde_json::to_string(&out).unwrap();
         assert_eq!(
             out,
             r#"{"jsonrpc":"2.0","method":"bar","params":["woo"],"id":1}"#
@@ -49,34 +36,131 @@ async fn basic() -> anyhow::Result<()> {
 
 #[tokio::test]
 async fn basic_mixed_id_types() -> anyhow::Result<()> {
-    struct Api {}
-
-    #[rpc(all_positional, ts_outdir = "typescript/generated")]
-    impl Api {
-        pub async fn upper(&self, text: String) -> String {
-            text.to_uppercase()
-        }
-    }
-
-    let (session, mut out_rx) = RpcSession::create(Api {});
+    let (session, mut out_rx) = session();
 
     let req = r#"{"jsonrpc":"2.0","method":"upper","params":["foo"],"id":"7"}"#;
     session.handle_incoming(req).await;
     let out = out_rx.next().await.unwrap();
-    let out = serde_json::to_string(&out).unwrap();
     assert_eq!(out, r#"{"jsonrpc":"2.0","id":"7","result":"FOO"}"#);
 
     let req = r#"{"jsonrpc":"2.0","method":"upper","params":["foo"],"id":9}"#;
     session.handle_incoming(req).await;
     let out = out_rx.next().await.unwrap();
-    let out = serde_json::to_string(&out).unwrap();
     assert_eq!(out, r#"{"jsonrpc":"2.0","id":9,"result":"FOO"}"#);
 
     let req = r#"{"jsonrpc":"2.0","method":"upper","params":["foo"],"id":"hi"}"#;
     session.handle_incoming(req).await;
     let out = out_rx.next().await.unwrap();
-    let out = serde_json::to_string(&out).unwrap();
     assert_eq!(out, r#"{"jsonrpc":"2.0","id":"hi","result":"FOO"}"#);
 
     Ok(())
 }
+
+#[tokio::test]
+async fn full_id_range() -> anyhow::Result<()> {
+    let (session, _out_rx) = session();
+
+    for id in ["-1", "5000000000", "-9223372036854775808", "null"] {
+        let id: serde_json::Value = id.parse()?;
+        let out = session
+            .process_incoming(&request(&id, "upper", ["foo"]))
+            .await
+            .unwrap();
+        assert_eq!(out, response(&id, "FOO"));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn invalid_messages() -> anyhow::Result<()> {
+    let (session, _out_rx) = session();
+
+    let cases = [
+        (
+            r#"{"jsonrpc":"2.0","method":"upper""#,
+            r#"{"jsonrpc":"2.0","id":null,"error":{"code":-32700,"message":"EOF while parsing an object at line 1 column 33"}}"#,
+        ),
+        (
+            r#"{"foo":1}"#,
+            r#"{"jsonrpc":"2.0","id":null,"error":{"code":-32600,"message":"Message is neither a request nor a response"}}"#,
+        ),
+        (
+            r#"{"jsonrpc":"1.0","method":"upper","id":3}"#,
+            r#"{"jsonrpc":"2.0","id":3,"error":{"code":-32600,"message":"Unsupported JSON-RPC version, expected \"2.0\""}}"#,
+        ),
+        (
+            r#"{"jsonrpc":"2.0","method":1,"id":"a"}"#,
+            r#"{"jsonrpc":"2.0","id":"a","error":{"code":-32600,"message":"Method must be a string"}}"#,
+        ),
+        (
+            r#"{"jsonrpc":"2.0","method":"upper","params":"foo","id":4}"#,
+            r#"{"jsonrpc":"2.0","id":4,"error":{"code":-32600,"message":"Params must be an object or array"}}"#,
+        ),
+        (
+            r#"1"#,
+            r#"{"jsonrpc":"2.0","id":null,"error":{"code":-32600,"message":"Message must be an object or array"}}"#,
+        ),
+        (
+            r#"[1,{"jsonrpc":"2.0","method":"upper","params":["foo"],"id":5}]"#,
+            r#"[{"jsonrpc":"2.0","id":5,"result":"FOO"},{"jsonrpc":"2.0","id":null,"error":{"code":-32600,"message":"Message must be an object or array"}}]"#,
+        ),
+    ];
+    for (req, expected) in cases {
+        let out = session.process_incoming(req).await.unwrap();
+        assert_eq!(out, expected);
+    }
+
+    Ok(())
+}
+
+#[derive(serde::Deserialize, yerpc::TypeDef)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+struct Geometry;
+
+#[rpc(ts_outdir = "../target/test-bindings/basic")]
+impl Geometry {
+    #[rpc(positional)]
+    pub async fn shift(&self, point: Point, by_value: i32) -> i32 {
+        point.x + point.y + by_value
+    }
+
+    pub async fn sum(&self, point: Point) -> i32 {
+        point.x + point.y
+    }
+}
+
+#[tokio::test]
+async fn invalid_params() -> anyhow::Result<()> {
+    let (session, _out_rx) = RpcSession::create(Geometry);
+
+    let cases = [
+        (
+            r#"{"jsonrpc":"2.0","method":"shift","params":[{"x":1,"y":2},"3"],"id":1}"#,
+            r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32602,"message":"Invalid parameter `byValue`: invalid type: string \"3\", expected i32","data":{"param":"byValue","position":1}}}"#,
+        ),
+        (
+            r#"{"jsonrpc":"2.0","method":"shift","params":[{"x":1,"y":"2"},3],"id":2}"#,
+            r#"{"jsonrpc":"2.0","id":2,"error":{"code":-32602,"message":"Invalid parameter `point`: invalid type: string \"2\", expected i32","data":{"param":"point","path":"y","position":0}}}"#,
+        ),
+        (
+            r#"{"jsonrpc":"2.0","method":"shift","params":{"point":{"x":1,"y":2}},"id":3}"#,
+            r#"{"jsonrpc":"2.0","id":3,"error":{"code":-32602,"message":"This method takes an array of 2 arguments"}}"#,
+        ),
+        (
+            r#"{"jsonrpc":"2.0","method":"sum","params":{"x":1},"id":4}"#,
+            r#"{"jsonrpc":"2.0","id":4,"error":{"code":-32602,"message":"Invalid parameter `point`: missing field `y`","data":{"param":"point"}}}"#,
+        ),
+    ];
+    for (req, expected) in cases {
+        let out = session.process_incoming(req).await.unwrap();
+        assert_eq!(out, expected);
+    }
+
+    Ok(())
+}
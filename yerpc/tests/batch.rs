@@ -0,0 +1,182 @@
+mod common;
+
+use common::{error, session};
+use futures_util::StreamExt;
+use std::time::Duration;
+use yerpc::{Error, QueuePolicy, RpcClient, RpcSession};
+
+#[tokio::test]
+async fn batch() -> anyhow::Result<()> {
+    let (session, _out_rx) = session();
+
+    let req = r#"[
+        {"jsonrpc":"2.0","method":"upper","params":["foo"],"id":1},
+        {"jsonrpc":"2.0","method":"ping"},
+        {"jsonrpc":"2.0","method":"upper","params":["bar"],"id":2}
+    ]"#;
+    let out = session.process_incoming(req).await.unwrap();
+    assert_eq!(
+        out,
+        r#"[{"jsonrpc":"2.0","id":1,"result":"FOO"},{"jsonrpc":"2.0","id":2,"result":"BAR"}]"#
+    );
+
+    // Batch of notifications has no response.
+    let req = r#"[{"jsonrpc":"2.0","method":"ping"},{"jsonrpc":"2.0","method":"ping"}]"#;
+    assert!(session.process_incoming(req).await.is_none());
+
+    let out = session.process_incoming("[]").await.unwrap();
+    assert_eq!(
+        out,
+        r#"{"jsonrpc":"2.0","id":null,"error":{"code":-32600,"message":"Invalid request"}}"#
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn client_batch() -> anyhow::Result<()> {
+    let (server, _server_out_rx) = session();
+    let (session, mut out_rx) = RpcSession::create(());
+    let client = session.client().clone();
+
+    let remote = tokio::spawn(async move {
+        let out = out_rx.next().await.unwrap();
+        let res = server.process_incoming(&out).await.unwrap();
+        session.handle_incoming(&res).await;
+        out
+    });
+
+    let mut batch = client.batch();
+    let foo = batch.request("upper", Some(["foo"]))?;
+    batch.notification("ping", None::<()>)?;
+    let bar = batch.request("upper", Some(["bar"]))?;
+    assert_eq!(batch.len(), 3);
+    batch.send().await?;
+    assert_eq!(bar.await?, "BAR");
+    assert_eq!(foo.await?, "FOO");
+    assert_eq!(
+        remote.await?,
+        r#"[{"jsonrpc":"2.0","method":"upper","params":["foo"],"id":1},{"jsonrpc":"2.0","method":"ping"},{"jsonrpc":"2.0","method":"upper","params":["bar"],"id":2}]"#
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn client_batch_rejected() -> anyhow::Result<()> {
+    let (session, out_rx) = RpcSession::create(());
+    let client = session.client().clone();
+
+    let mut batch = client.batch();
+    let foo = batch.request("upper", Some(["foo"]))?;
+    let bar = batch.request("upper", Some(["bar"]))?;
+    batch.send().await?;
+    out_rx.try_recv()?;
+
+    // An error without ID rejects the whole batch.
+    session
+        .handle_incoming(&error(
+            None::<()>,
+            Error::INVALID_REQUEST,
+            "Invalid request",
+        ))
+        .await;
+    assert_eq!(foo.await.unwrap_err().code, Error::INVALID_REQUEST);
+    assert_eq!(bar.await.unwrap_err().code, Error::INVALID_REQUEST);
+    Ok(())
+}
+
+#[tokio::test]
+async fn client_batch_failures() -> anyhow::Result<()> {
+    let (client, out_rx) = RpcClient::builder()
+        .queue_capacity(1)
+        .queue_policy(QueuePolicy::Fail)
+        .build();
+    let client = client.with_timeout(Duration::from_millis(50));
+    client.send_notification("tick", None::<()>).await?;
+
+    // Requests fail with the error of sending the batch.
+    let mut batch = client.batch();
+    let foo = batch.request("upper", Some(["foo"]))?;
+    let err = batch.send().await.unwrap_err();
+    assert_eq!(err.code, Error::QUEUE_FULL);
+    assert_eq!(foo.await.unwrap_err().code, Error::QUEUE_FULL);
+
+    // Requests time out like single requests.
+    out_rx.try_recv()?;
+    let mut batch = client.batch();
+    let foo = batch.request("upper", Some(["foo"]))?;
+    batch.send().await?;
+    out_rx.try_recv()?;
+    assert!(foo.await.unwrap_err().is_timeout());
+    assert_eq!(
+        out_rx.try_recv()?,
+        r#"{"jsonrpc":"2.0","method":"$/cancelRequest","params":{"id":2}}"#
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn client_batch_routing() -> anyhow::Result<()> {
+    let (session, out_rx) = RpcSession::create(());
+    let client = session.client().clone();
+
+    let mut batch = client.batch();
+    let foo = batch.request("upper", Some(["foo"]))?;
+    let bar = batch.request("upper", Some(["bar"]))?;
+    batch.send().await?;
+    let mut batch = client.batch();
+    let baz = batch.request("upper", Some(["baz"]))?;
+    batch.send().await?;
+    out_rx.try_recv()?;
+    out_rx.try_recv()?;
+
+    // Errors without ID inside a reply array do not affect the other responses.
+    session
+        .handle_incoming(&format!(
+            "[{},{}]",
+            error(None::<()>, Error::INVALID_REQUEST, "Invalid request"),
+            common::response(1, "FOO")
+        ))
+        .await;
+    assert_eq!(foo.await?, "FOO");
+
+    // A single error without ID rejects the only batch without responses.
+    session
+        .handle_incoming(&error(None::<()>, Error::PARSE_ERROR, "Parse error"))
+        .await;
+    assert_eq!(baz.await.unwrap_err().code, Error::PARSE_ERROR);
+    session
+        .handle_incoming(&format!("[{}]", common::response(2, "BAR")))
+        .await;
+    assert_eq!(bar.await?, "BAR");
+    Ok(())
+}
+
+#[tokio::test]
+async fn client_batch_ambiguous_rejection() -> anyhow::Result<()> {
+    let (session, out_rx) = RpcSession::create(());
+    let client = session
+        .client()
+        .clone()
+        .with_timeout(Duration::from_millis(50));
+
+    let mut batch = client.batch();
+    let foo = batch.request("upper", Some(["foo"]))?;
+    batch.send().await?;
+    let mut batch = client.batch();
+    let bar = batch.request("upper", Some(["bar"]))?;
+    batch.send().await?;
+    out_rx.try_recv()?;
+    out_rx.try_recv()?;
+
+    // The error cannot be matched to one of the batches, so both stay pending.
+    session
+        .handle_incoming(&error(None::<()>, Error::PARSE_ERROR, "Parse error"))
+        .await;
+    session
+        .handle_incoming(&format!("[{}]", common::response(2, "BAR")))
+        .await;
+    assert_eq!(bar.await?, "BAR");
+    assert!(foo.await.unwrap_err().is_timeout());
+    Ok(())
+}
@@ -0,0 +1,169 @@
+mod common;
+
+use common::{error, request, session};
+use futures_util::StreamExt;
+use std::time::Duration;
+use yerpc::{Error, RpcSession};
+
+#[tokio::test]
+async fn request_timeout() -> anyhow::Result<()> {
+    let (session, mut out_rx) = RpcSession::create(());
+    let client = session
+        .client()
+        .clone()
+        .with_timeout(Duration::from_millis(50));
+
+    let err = client.send_request("bar", Some(["woo"])).await.unwrap_err();
+    assert!(err.is_timeout());
+    assert_eq!(err.code, Error::TIMEOUT);
+    let out = out_rx.next().await.unwrap();
+    assert_eq!(out, request(1, "bar", ["woo"]));
+
+    // Late response is ignored.
+    session
+        .handle_incoming(r#"{"jsonrpc":"2.0","id":1,"result":"boo"}"#)
+        .await;
+
+    // Per-call timeout overrides the default.
+    tokio::spawn(async move {
+        let _ = out_rx.next().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        session
+            .handle_incoming(r#"{"jsonrpc":"2.0","id":2,"result":"boo"}"#)
+            .await;
+    });
+    let res = client
+        .send_request_with_timeout("bar", Some(["woo"]), Some(Duration::from_secs(5)))
+        .await?;
+    assert_eq!(res, "boo");
+    Ok(())
+}
+
+#[tokio::test]
+async fn cancel_request() -> anyhow::Result<()> {
+    let (session, mut out_rx) = session();
+
+    // Server side: abort a running handler.
+    let handler = tokio::spawn({
+        let session = session.clone();
+        async move { session.process_incoming(&request(5, "forever", ())).await }
+    });
+    tokio::task::yield_now().await;
+    let res = session
+        .process_incoming(r#"{"jsonrpc":"2.0","method":"$/cancelRequest","params":{"id":5}}"#)
+        .await;
+    assert!(res.is_none());
+    let out = handler.await?.unwrap();
+    assert_eq!(out, error(5, Error::REQUEST_CANCELLED, "Request cancelled"));
+
+    // Client side: dropping the future sends a cancellation.
+    let client = session.client().clone();
+    let res = client.start_request("bar", None::<()>).await?;
+    let out = out_rx.next().await.unwrap();
+    assert_eq!(out, request(1, "bar", ()));
+    drop(res);
+    let out = out_rx.next().await.unwrap();
+    assert_eq!(
+        out,
+        r#"{"jsonrpc":"2.0","method":"$/cancelRequest","params":{"id":1}}"#
+    );
+
+    // Client side: explicit cancellation.
+    let res = client.start_request("bar", None::<()>).await?;
+    let _ = out_rx.next().await.unwrap();
+    client.cancel_request(res.id()).await?;
+    let out = out_rx.next().await.unwrap();
+    assert_eq!(
+        out,
+        r#"{"jsonrpc":"2.0","method":"$/cancelRequest","params":{"id":2}}"#
+    );
+    assert!(res.await.unwrap_err().is_cancelled());
+    Ok(())
+}
+
+#[tokio::test]
+async fn server_busy() -> anyhow::Result<()> {
+    let (session, _out_rx) = session();
+    let session = session.with_max_concurrent_requests(1);
+
+    let handler = tokio::spawn({
+        let session = session.clone();
+        async move { session.process_incoming(&request(1, "forever", ())).await }
+    });
+    tokio::task::yield_now().await;
+    assert_eq!(session.in_flight_requests(), 1);
+
+    let out = session
+        .process_incoming(&request(2, "forever", ()))
+        .await
+        .unwrap();
+    assert_eq!(out, error(2, Error::SERVER_BUSY, "Server busy"));
+
+    handler.abort();
+    let _ = handler.await;
+    assert_eq!(session.in_flight_requests(), 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn timeout_before_sending() -> anyhow::Result<()> {
+    use yerpc::{QueuePolicy, RpcClient};
+
+    let (client, out_rx) = RpcClient::builder()
+        .queue_capacity(1)
+        .queue_policy(QueuePolicy::Block)
+        .build();
+    let client = client.with_timeout(Duration::from_millis(50));
+    client.send_notification("tick", None::<()>).await?;
+
+    // The request times out while waiting for room in the queue.
+    let err = client.send_request("bar", None::<()>).await.unwrap_err();
+    assert!(err.is_timeout());
+    assert_eq!(out_rx.try_recv()?, common::notification("tick", ()));
+    // A request that never went out is not cancelled.
+    assert!(out_rx.try_recv().is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn duplicate_and_null_ids() -> anyhow::Result<()> {
+    let (session, _out_rx) = session();
+
+    let first = tokio::spawn({
+        let session = session.clone();
+        async move { session.process_incoming(&request(5, "forever", ())).await }
+    });
+    let null = tokio::spawn({
+        let session = session.clone();
+        async move { session.process_incoming(&request((), "forever", ())).await }
+    });
+    tokio::task::yield_now().await;
+
+    // A request reusing the ID of a running one is rejected
+    // without unregistering the running one.
+    let out = session
+        .process_incoming(&request(5, "upper", ["foo"]))
+        .await;
+    assert_eq!(
+        out.unwrap(),
+        error(5, Error::INVALID_REQUEST, "Request ID is already in use")
+    );
+    // Requests with a null ID run side by side and cannot be cancelled.
+    let out = session
+        .process_incoming(&request((), "upper", ["foo"]))
+        .await;
+    assert_eq!(out.unwrap(), common::response((), "FOO"));
+    session
+        .process_incoming(r#"{"jsonrpc":"2.0","method":"$/cancelRequest","params":{"id":null}}"#)
+        .await;
+
+    session
+        .process_incoming(r#"{"jsonrpc":"2.0","method":"$/cancelRequest","params":{"id":5}}"#)
+        .await;
+    let out = first.await?.unwrap();
+    assert_eq!(out, error(5, Error::REQUEST_CANCELLED, "Request cancelled"));
+    tokio::task::yield_now().await;
+    assert!(!null.is_finished());
+    null.abort();
+    Ok(())
+}
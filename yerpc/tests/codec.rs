@@ -0,0 +1,64 @@
+mod common;
+
+use common::{request, response, Api};
+use std::sync::Arc;
+use yerpc::codec::{self, Codec, Json};
+use yerpc::{Error, RpcSession};
+
+/// JSON in binary frames, for peers that expect binary frames.
+struct Binary;
+
+impl Codec for Binary {
+    fn subprotocol(&self) -> &'static str {
+        "jsonrpc.binary"
+    }
+
+    fn is_binary(&self) -> bool {
+        true
+    }
+
+    fn encode(&self, message: String) -> Result<Vec<u8>, Error> {
+        Ok(message.into_bytes())
+    }
+
+    fn decode(&self, frame: Vec<u8>) -> Result<String, Error> {
+        String::from_utf8(frame).map_err(|err| Error::new(Error::PARSE_ERROR, err.to_string()))
+    }
+}
+
+#[tokio::test]
+async fn custom_codec() -> anyhow::Result<()> {
+    let (session, out_rx) = RpcSession::builder(Api::new()).codec(Binary).build();
+    let codec = session.client().codec().clone();
+    assert!(codec.is_binary());
+    let frame = codec.encode(request(1, "upper", ["foo"]))?;
+    session.handle_incoming_frame(&*codec, frame).await;
+    assert_eq!(out_rx.try_recv()?, response(1, "FOO"));
+
+    // Frames that cannot be decoded are answered with a parse error.
+    session.handle_incoming_frame(&*codec, vec![0xff]).await;
+    let out: yerpc::Message = out_rx.try_recv()?.parse().unwrap();
+    match out {
+        yerpc::Message::Response(response) => {
+            assert_eq!(response.error.unwrap().code, Error::PARSE_ERROR)
+        }
+        message => panic!("Unexpected message {message:?}"),
+    }
+    Ok(())
+}
+
+#[test]
+fn negotiation() {
+    let preferred: Arc<dyn Codec> = Arc::new(Binary);
+    assert_eq!(codec::offer(&preferred), "jsonrpc.binary, jsonrpc");
+    let json: Arc<dyn Codec> = Arc::new(Json);
+    assert_eq!(codec::offer(&json), "jsonrpc");
+
+    let negotiated = |offered| codec::negotiate(offered, &preferred).map(|c| c.subprotocol());
+    assert_eq!(
+        negotiated("jsonrpc, jsonrpc.binary"),
+        Some("jsonrpc.binary")
+    );
+    assert_eq!(negotiated("jsonrpc"), Some("jsonrpc"));
+    assert_eq!(negotiated("jsonrpc.other"), None);
+}
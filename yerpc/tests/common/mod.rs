@@ -0,0 +1,145 @@
+//! API and helpers shared by the integration tests.
+#![allow(dead_code)]
+
+use futures::stream::{self, BoxStream, Stream};
+use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use yerpc::{rpc, Error, OutReceiver, Progress, RpcClient, RpcSession};
+
+/// API exercising every kind of method.
+#[derive(Clone)]
+pub struct Api {
+    gate: (async_channel::Sender<()>, async_channel::Receiver<()>),
+    pings: Arc<AtomicUsize>,
+}
+
+impl Api {
+    pub fn new() -> Self {
+        Self {
+            gate: async_channel::unbounded(),
+            pings: Default::default(),
+        }
+    }
+
+    /// Returns a sender releasing one call to `wait` per message.
+    pub fn gate(&self) -> async_channel::Sender<()> {
+        self.gate.0.clone()
+    }
+
+    /// Returns the number of `ping` notifications received.
+    pub fn pings(&self) -> usize {
+        self.pings.load(Ordering::SeqCst)
+    }
+}
+
+#[rpc(all_positional, ts_outdir = "../target/test-bindings/common")]
+impl Api {
+    pub async fn constant(&self) -> String {
+        "example".to_string()
+    }
+
+    pub async fn upper(&self, text: String) -> String {
+        text.to_uppercase()
+    }
+
+    /// Returns `text`, failing with code 1 if it is empty.
+    pub async fn echo(&self, text: String) -> yerpc::Result<String> {
+        match text.is_empty() {
+            true => Err(Error::new(1, "Empty".to_string())),
+            false => Ok(text),
+        }
+    }
+
+    pub async fn fail(&self) -> yerpc::Result<()> {
+        Err(Error::new(1, "failed".to_string()))
+    }
+
+    /// Waits until released with [`Api::gate`].
+    pub async fn wait(&self) -> String {
+        self.gate.1.recv().await.unwrap();
+        "done".to_string()
+    }
+
+    /// Asks the client for the answer to `question`.
+    pub async fn ask(
+        &self,
+        #[rpc(context)] client: RpcClient,
+        question: String,
+    ) -> yerpc::Result<serde_json::Value> {
+        client.send_request("answer", Some([question])).await
+    }
+
+    pub async fn forever(&self) {
+        futures::future::pending::<()>().await
+    }
+
+    pub async fn export(
+        &self,
+        #[rpc(context)] progress: Progress<u32>,
+        files: u32,
+    ) -> yerpc::Result<String> {
+        for i in 1..=files {
+            progress.report(i).await?;
+        }
+        Ok("done".to_string())
+    }
+
+    #[rpc(notification)]
+    pub async fn ping(&self) {
+        self.pings.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[rpc(notification)]
+    pub async fn warn(&self) -> yerpc::Result<()> {
+        Err(Error::new(1, "failed".to_string()))
+    }
+
+    #[rpc(subscription)]
+    pub async fn count(&self, n: u32) -> impl Stream<Item = u32> {
+        stream::iter(1..=n)
+    }
+
+    #[rpc(subscription)]
+    pub async fn watch(&self) -> yerpc::Result<BoxStream<'static, String>> {
+        Ok(Box::pin(stream::pending()))
+    }
+}
+
+/// Creates a session serving [`Api`].
+pub fn session() -> (RpcSession<Api>, OutReceiver) {
+    RpcSession::create(Api::new())
+}
+
+/// Returns a request as serialized by yerpc, omitting null params.
+pub fn request(id: impl Serialize, method: &str, params: impl Serialize) -> String {
+    let id = serde_json::to_string(&id).unwrap();
+    match serde_json::to_value(params).unwrap() {
+        serde_json::Value::Null => format!(r#"{{"jsonrpc":"2.0","method":"{method}","id":{id}}}"#),
+        params => {
+            format!(r#"{{"jsonrpc":"2.0","method":"{method}","params":{params},"id":{id}}}"#)
+        }
+    }
+}
+
+/// Returns a notification as serialized by yerpc, omitting null params.
+pub fn notification(method: &str, params: impl Serialize) -> String {
+    match serde_json::to_value(params).unwrap() {
+        serde_json::Value::Null => format!(r#"{{"jsonrpc":"2.0","method":"{method}"}}"#),
+        params => format!(r#"{{"jsonrpc":"2.0","method":"{method}","params":{params}}}"#),
+    }
+}
+
+/// Returns a successful response as serialized by yerpc.
+pub fn response(id: impl Serialize, result: impl Serialize) -> String {
+    let id = serde_json::to_string(&id).unwrap();
+    let result = serde_json::to_string(&result).unwrap();
+    format!(r#"{{"jsonrpc":"2.0","id":{id},"result":{result}}}"#)
+}
+
+/// Returns an error response without data as serialized by yerpc.
+pub fn error(id: impl Serialize, code: i32, message: &str) -> String {
+    let id = serde_json::to_string(&id).unwrap();
+    let message = serde_json::to_string(message).unwrap();
+    format!(r#"{{"jsonrpc":"2.0","id":{id},"error":{{"code":{code},"message":{message}}}}}"#)
+}
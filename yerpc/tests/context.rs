@@ -0,0 +1,81 @@
+use serde::Deserialize;
+use yerpc::{rpc, Meta, MethodName, RequestId, RpcClient, RpcSession};
+
+#[derive(Clone)]
+struct User(String);
+
+/// Param sharing its name with [`yerpc::Progress`].
+#[derive(Deserialize, yerpc::TypeDef)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
+struct Progress {
+    done: u32,
+}
+
+struct Api;
+
+#[rpc(all_positional, ts_outdir = "../target/test-bindings/context")]
+impl Api {
+    pub async fn whoami(
+        &self,
+        #[rpc(context)] RequestId(id): RequestId,
+        greeting: String,
+        #[rpc(context)] MethodName(method): MethodName,
+        #[rpc(context)] Meta(User(user)): Meta<User>,
+    ) -> String {
+        format!("{greeting} {user} from {method} {id:?}")
+    }
+
+    pub async fn echo(&self, #[rpc(context)] client: RpcClient, text: String) -> yerpc::Result<()> {
+        client.send_notification("echo", Some([text])).await
+    }
+
+    pub async fn missing(&self, #[rpc(context)] Meta(n): Meta<u32>) -> u32 {
+        n
+    }
+
+    pub async fn advance(&self, progress: Progress) -> u32 {
+        progress.done + 1
+    }
+}
+
+#[tokio::test]
+async fn context_extractors() -> anyhow::Result<()> {
+    let (session, out_rx) = RpcSession::create(Api);
+    let session = session.with_metadata(User("alice".to_string()));
+
+    let res = session
+        .process_incoming(r#"{"jsonrpc":"2.0","method":"whoami","params":["hi"],"id":1}"#)
+        .await;
+    let out = res.unwrap();
+    assert_eq!(
+        out,
+        r#"{"jsonrpc":"2.0","id":1,"result":"hi alice from whoami Number(1)"}"#
+    );
+
+    let res = session
+        .process_incoming(r#"{"jsonrpc":"2.0","method":"echo","params":["hello"],"id":2}"#)
+        .await;
+    let out = res.unwrap();
+    assert_eq!(out, r#"{"jsonrpc":"2.0","id":2,"result":null}"#);
+    let out = out_rx.try_recv().unwrap();
+    assert_eq!(
+        out,
+        r#"{"jsonrpc":"2.0","method":"echo","params":["hello"]}"#
+    );
+
+    let res = session
+        .process_incoming(r#"{"jsonrpc":"2.0","method":"missing","id":3}"#)
+        .await;
+    let out = res.unwrap();
+    assert_eq!(
+        out,
+        r#"{"jsonrpc":"2.0","id":3,"error":{"code":-32603,"message":"Missing connection metadata `u32`"}}"#
+    );
+
+    // Arguments not marked as context are params, whatever their type name.
+    let res = session
+        .process_incoming(r#"{"jsonrpc":"2.0","method":"advance","params":[{"done":1}],"id":4}"#)
+        .await;
+    assert_eq!(res.unwrap(), r#"{"jsonrpc":"2.0","id":4,"result":2}"#);
+    Ok(())
+}
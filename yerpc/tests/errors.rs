@@ -0,0 +1,144 @@
+use yerpc::typescript::type_def::{write_definition_file, DefinitionFileOptions};
+use yerpc::{rpc, Error, RpcError, RpcSession};
+
+#[derive(yerpc::RpcError)]
+enum AccountError {
+    #[rpc_error(code = 1000, message = "Account not found")]
+    NotFound,
+    #[rpc_error(code = -1001)]
+    Locked(String),
+    #[rpc_error(code = 1002, message = "Quota exceeded")]
+    QuotaExceeded { used: u64, limit: u64 },
+}
+
+/// Generic error enum.
+#[derive(yerpc::RpcError)]
+enum LimitError<T> {
+    #[rpc_error(code = 1003, message = "Limit exceeded")]
+    Exceeded { limit: T },
+}
+
+/// Error converted into [`Error`] without implementing [`RpcError`].
+struct PlainError;
+
+impl From<PlainError> for Error {
+    fn from(_: PlainError) -> Self {
+        Error::new(7, "Plain".to_string())
+    }
+}
+
+/// Data whose serialization always fails.
+struct Unserializable;
+
+impl serde::Serialize for Unserializable {
+    fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+        Err(serde::ser::Error::custom("broken"))
+    }
+}
+
+struct Api;
+
+#[rpc(all_positional, ts_outdir = "../target/test-bindings/errors")]
+impl Api {
+    #[rpc(typed_error)]
+    pub async fn account(&self, id: u32) -> Result<String, AccountError> {
+        match id {
+            0 => Err(AccountError::NotFound),
+            1 => Err(AccountError::Locked("admin".to_string())),
+            2 => Err(AccountError::QuotaExceeded {
+                used: 12,
+                limit: 10,
+            }),
+            _ => Ok("alice".to_string()),
+        }
+    }
+
+    #[rpc(typed_error)]
+    pub async fn limit(&self, n: u32) -> Result<u32, LimitError<u32>> {
+        match n {
+            0..=10 => Ok(n),
+            _ => Err(LimitError::Exceeded { limit: 10 }),
+        }
+    }
+
+    pub async fn plain(&self) -> Result<(), PlainError> {
+        Err(PlainError)
+    }
+}
+
+#[tokio::test]
+async fn typed_errors() -> anyhow::Result<()> {
+    let (session, _out_rx) = RpcSession::create(Api);
+    let mut results = vec![];
+    for id in 0..4 {
+        let request =
+            format!(r#"{{"jsonrpc":"2.0","method":"account","params":[{id}],"id":{id}}}"#);
+        let res = session.process_incoming(&request).await.unwrap();
+        results.push(res);
+    }
+    assert_eq!(
+        results,
+        [
+            r#"{"jsonrpc":"2.0","id":0,"error":{"code":1000,"message":"Account not found"}}"#,
+            r#"{"jsonrpc":"2.0","id":1,"error":{"code":-1001,"message":"Locked","data":"admin"}}"#,
+            r#"{"jsonrpc":"2.0","id":2,"error":{"code":1002,"message":"Quota exceeded","data":{"limit":10,"used":12}}}"#,
+            r#"{"jsonrpc":"2.0","id":3,"result":"alice"}"#,
+        ]
+    );
+
+    let codes: Vec<_> = AccountError::VARIANTS
+        .iter()
+        .map(|variant| (variant.name, variant.code))
+        .collect();
+    assert_eq!(
+        codes,
+        [
+            ("NotFound", 1000),
+            ("Locked", -1001),
+            ("QuotaExceeded", 1002)
+        ]
+    );
+    assert_eq!(
+        yerpc::typescript::error_codes_to_string::<AccountError>(),
+        "export const AccountError = {\n  NotFound: 1000,\n  Locked: -1001,\n  QuotaExceeded: 1002,\n} as const;\n"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn untyped_and_generic_errors() -> anyhow::Result<()> {
+    let (session, _out_rx) = RpcSession::create(Api);
+    let res = session
+        .process_incoming(r#"{"jsonrpc":"2.0","method":"limit","params":[11],"id":1}"#)
+        .await;
+    assert_eq!(
+        res.unwrap(),
+        r#"{"jsonrpc":"2.0","id":1,"error":{"code":1003,"message":"Limit exceeded","data":{"limit":10}}}"#
+    );
+    let res = session
+        .process_incoming(r#"{"jsonrpc":"2.0","method":"plain","id":2}"#)
+        .await;
+    assert_eq!(
+        res.unwrap(),
+        r#"{"jsonrpc":"2.0","id":2,"error":{"code":7,"message":"Plain"}}"#
+    );
+
+    // Generic error enums are defined with their type parameters.
+    let mut out = Vec::new();
+    let options = DefinitionFileOptions {
+        header: None,
+        root_namespace: None,
+    };
+    write_definition_file::<_, LimitError<u32>>(&mut out, options)?;
+    let out = String::from_utf8(out)?;
+    assert!(out.contains("export type LimitError<T> = ("), "{out}");
+    assert!(out.contains(r#""limit": T;"#), "{out}");
+
+    // Data that fails to serialize is reported as an internal error.
+    let err = Error::from(LimitError::Exceeded {
+        limit: Unserializable,
+    });
+    assert_eq!(err.code, Error::INTERNAL_ERROR);
+    assert!(err.message.contains("broken"), "{}", err.message);
+    Ok(())
+}
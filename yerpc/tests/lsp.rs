@@ -0,0 +1,147 @@
+mod common;
+
+#[cfg(all(test, feature = "support-lsp"))]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use yerpc::lsp::{encode_frame, serve_framed, FrameDecoder, FrameError, MAX_HEADER_LENGTH};
+    use yerpc::{Error, Message, TransportError};
+
+    #[test]
+    fn test_decoder() {
+        let mut decoder = FrameDecoder::new(100);
+        let mut buf = Vec::new();
+
+        // Frames are decoded from partial reads.
+        for byte in encode_frame(r#"{"a":1}"#) {
+            assert_eq!(decoder.decode(&mut buf), Ok(None));
+            buf.push(byte);
+        }
+        assert_eq!(decoder.decode(&mut buf), Ok(Some(r#"{"a":1}"#.to_string())));
+        assert!(buf.is_empty());
+
+        let frames: &[(&[u8], FrameError)] = &[
+            (
+                b"Content-Length: 2\r\nContent-Type: text/plain; charset=latin1\r\n\r\n{}",
+                FrameError::UnsupportedContentType("text/plain; charset=latin1".to_string()),
+            ),
+            (b"Content-Length: 101\r\n\r\n", FrameError::TooLarge(101)),
+            (
+                b"content-length: 2\r\n\r\n\xff\xff",
+                FrameError::InvalidUtf8,
+            ),
+        ];
+        for (frame, expected) in frames {
+            // The next valid frame is decoded after the error.
+            assert!(!expected.is_fatal());
+            buf.extend_from_slice(frame);
+            if let FrameError::TooLarge(length) = expected {
+                buf.resize(buf.len() + length, b'x');
+            }
+            buf.extend_from_slice(&encode_frame("[]"));
+            assert_eq!(decoder.decode(&mut buf).as_ref(), Err(expected));
+            assert_eq!(decoder.decode(&mut buf), Ok(Some("[]".to_string())));
+            assert!(buf.is_empty());
+        }
+
+        // Without a valid length the end of the frame is unknown.
+        let frames: &[(&[u8], FrameError)] = &[
+            (
+                b"Content-Length: 1x\r\n\r\n{}",
+                FrameError::InvalidContentLength("1x".to_string()),
+            ),
+            (
+                b"Content-Length: 2\r\nContent-Length: 2\r\n\r\n{}",
+                FrameError::InvalidContentLength("2".to_string()),
+            ),
+            (
+                b"Content-Type: application/json\r\n\r\n{}",
+                FrameError::MissingContentLength,
+            ),
+            (
+                b"Content-Length 2\r\n\r\n{}",
+                FrameError::InvalidHeader("Content-Length 2".to_string()),
+            ),
+            (&[b'x'; MAX_HEADER_LENGTH + 1], FrameError::HeaderTooLong),
+        ];
+        for (frame, expected) in frames {
+            let mut decoder = FrameDecoder::new(100);
+            assert!(expected.is_fatal());
+            let mut buf = frame.to_vec();
+            assert_eq!(decoder.decode(&mut buf).as_ref(), Err(expected));
+            buf.extend_from_slice(&encode_frame("[]"));
+            assert_eq!(decoder.decode(&mut buf).as_ref(), Err(expected));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_framed() -> anyhow::Result<()> {
+        let (session, out_rx) = crate::common::session();
+        let (mut input, reader) = tokio::io::duplex(16);
+        let (writer, mut output) = tokio::io::duplex(1024);
+        let server = tokio::spawn(serve_framed(session, out_rx, reader, writer, 100));
+
+        let request = r#"{"jsonrpc":"2.0","method":"upper","params":["foo"],"id":1}"#;
+        input.write_all(&encode_frame(request)).await?;
+        // Invalid frames are answered with a parse error.
+        input
+            .write_all(b"Content-Length: 2\r\nContent-Type: text/plain; charset=latin1\r\n\r\n{}")
+            .await?;
+        let request = r#"{"jsonrpc":"2.0","method":"upper","params":["bar"],"id":2}"#;
+        input.write_all(&encode_frame(request)).await?;
+        drop(input);
+        server.await??;
+
+        let mut out = Vec::new();
+        output.read_to_end(&mut out).await?;
+        let mut decoder = FrameDecoder::new(1000);
+        assert_eq!(
+            decoder.decode(&mut out),
+            Ok(Some(
+                r#"{"jsonrpc":"2.0","id":1,"result":"FOO"}"#.to_string()
+            ))
+        );
+        let error: Message = decoder.decode(&mut out).unwrap().unwrap().parse().unwrap();
+        match error {
+            Message::Response(response) => {
+                assert_eq!(response.id, None);
+                assert_eq!(response.error.unwrap().code, Error::PARSE_ERROR);
+            }
+            message => panic!("Unexpected message {message:?}"),
+        }
+        assert_eq!(
+            decoder.decode(&mut out),
+            Ok(Some(
+                r#"{"jsonrpc":"2.0","id":2,"result":"BAR"}"#.to_string()
+            ))
+        );
+        assert!(out.is_empty());
+
+        // Headers without a valid length end the connection.
+        let (session, out_rx) = crate::common::session();
+        let (mut input, reader) = tokio::io::duplex(1024);
+        let (writer, _output) = tokio::io::duplex(1024);
+        input.write_all(b"Content-Length: x\r\n\r\n{}").await?;
+        let res = serve_framed(session, out_rx, reader, writer, 100).await;
+        match res {
+            Err(TransportError::Recv(err)) => {
+                assert_eq!(err.kind(), std::io::ErrorKind::InvalidData)
+            }
+            res => panic!("Unexpected result {res:?}"),
+        }
+
+        // Streams ending within a frame fail.
+        let (session, out_rx) = crate::common::session();
+        let (mut input, reader) = tokio::io::duplex(1024);
+        let (writer, _output) = tokio::io::duplex(1024);
+        input.write_all(b"Content-Length: 10\r\n\r\n{}").await?;
+        drop(input);
+        let res = serve_framed(session, out_rx, reader, writer, 100).await;
+        match res {
+            Err(TransportError::Recv(err)) => {
+                assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof)
+            }
+            res => panic!("Unexpected result {res:?}"),
+        }
+        Ok(())
+    }
+}
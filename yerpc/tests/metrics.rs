@@ -0,0 +1,81 @@
+mod common;
+
+use common::{notification, request, session};
+use yerpc::{Metrics, MetricsSnapshot};
+
+#[tokio::test]
+async fn metrics() -> anyhow::Result<()> {
+    let metrics = Metrics::new();
+    let (session, out_rx) = session();
+    let session = session.with_metrics(metrics.clone());
+    for req in [
+        request(1, "echo", ["hi"]),
+        request(2, "echo", [""]),
+        notification("ping", ()),
+    ] {
+        session.handle_incoming(&req).await;
+    }
+    assert_eq!(out_rx.len(), 2);
+
+    // Outgoing requests are answered by the remote side.
+    let client = session.client().clone();
+    let remote = session.clone();
+    tokio::spawn(async move {
+        while let Ok(message) = out_rx.recv().await {
+            if let Ok(yerpc::Message::Request(request)) = message.parse() {
+                let response = yerpc::Response::success(request.id.unwrap(), "pong".into());
+                remote
+                    .handle_incoming(&serde_json::to_string(&response).unwrap())
+                    .await;
+            }
+        }
+    });
+    assert_eq!(client.send_request("ping", None::<()>).await?, "pong");
+
+    // Calls that are dropped before they finish are counted apart.
+    let handler = tokio::spawn({
+        let session = session.clone();
+        async move { session.process_incoming(&request(3, "forever", ())).await }
+    });
+    tokio::task::yield_now().await;
+    handler.abort();
+    let _ = handler.await;
+
+    let snapshot = metrics.snapshot();
+    let echo = &snapshot.incoming["echo"];
+    assert_eq!(echo.calls, 2);
+    assert_eq!(echo.errors.get(&1), Some(&1));
+    assert_eq!(echo.in_flight, 0);
+    assert_eq!(echo.latency.count(), 2);
+    assert_eq!(echo.params_bytes, 6 + 4);
+    assert_eq!(echo.result_bytes, 4);
+    let ping = &snapshot.incoming["ping"];
+    assert_eq!((ping.calls, ping.errors.len()), (1, 0));
+    // Outgoing requests are measured by the size of their messages.
+    let ping = &snapshot.outgoing["ping"];
+    assert_eq!(ping.calls, 1);
+    assert_eq!(
+        ping.params_bytes,
+        r#"{"jsonrpc":"2.0","method":"ping","id":1}"#.len() as u64
+    );
+    assert_eq!(
+        ping.result_bytes,
+        r#"{"jsonrpc":"2.0","id":1,"result":"pong"}"#.len() as u64
+    );
+    let forever = &snapshot.incoming["forever"];
+    assert_eq!(
+        (forever.calls, forever.dropped, forever.in_flight),
+        (0, 1, 0)
+    );
+
+    // Snapshots can be returned from RPC methods.
+    let json = serde_json::to_value(&snapshot)?;
+    assert_eq!(json["incoming"]["echo"]["errors"]["1"], 1);
+    assert_eq!(json["incoming"]["echo"]["inFlight"], 0);
+    let parsed: MetricsSnapshot = serde_json::from_value(json)?;
+    assert_eq!(parsed, snapshot);
+
+    metrics.reset();
+    assert_eq!(metrics.snapshot(), MetricsSnapshot::default());
+    Ok(())
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use common::{error, notification, request, response, session};
+use std::sync::{Arc, Mutex};
+use yerpc::{Error, Id, Middleware, Next, RpcCall, SessionError, TimingMiddleware};
+
+#[tokio::test]
+async fn error_hook() -> anyhow::Result<()> {
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let (session, _out_rx) = session();
+    let session = session.with_error_hook({
+        let errors = errors.clone();
+        move |err| errors.lock().unwrap().push(err)
+    });
+
+    let res = session.process_incoming(&notification("warn", ())).await;
+    assert!(res.is_none());
+    let res = session.process_incoming(&notification("missing", ())).await;
+    assert!(res.is_none());
+    let res = session.process_incoming(&response(42, "boo")).await;
+    assert!(res.is_none());
+
+    let errors = errors.lock().unwrap();
+    assert_eq!(errors.len(), 3);
+    assert!(matches!(
+        &errors[0],
+        SessionError::Notification { method, error } if method == "warn" && error.code == 1
+    ));
+    assert!(matches!(
+        &errors[1],
+        SessionError::Notification { method, error }
+            if method == "missing" && error.code == Error::METHOD_NOT_FOUND
+    ));
+    assert!(matches!(
+        &errors[2],
+        SessionError::UnknownResponse(response) if response.id == Some(Id::Number(42))
+    ));
+    Ok(())
+}
+
+/// Records calls in `log` with a label before and after the handler.
+struct Record {
+    label: &'static str,
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+#[yerpc::async_trait]
+impl Middleware for Record {
+    async fn handle(&self, call: RpcCall, next: Next<'_>) -> yerpc::Result<yerpc::RawResult> {
+        let entry = format!("{} {} {:?}", self.label, call.method, call.id);
+        self.log.lock().unwrap().push(entry);
+        let res = next.run(call).await;
+        let entry = format!("{} done {:?}", self.label, res.as_ref().map_err(|e| e.code));
+        self.log.lock().unwrap().push(entry);
+        res
+    }
+}
+
+/// Rejects calls to `constant`.
+struct Auth;
+
+#[yerpc::async_trait]
+impl Middleware for Auth {
+    async fn handle(&self, call: RpcCall, next: Next<'_>) -> yerpc::Result<yerpc::RawResult> {
+        if call.method == "constant" {
+            return Err(Error::new(403, "Forbidden".to_string()));
+        }
+        next.run(call).await
+    }
+}
+
+#[tokio::test]
+async fn middleware() -> anyhow::Result<()> {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let timed = Arc::new(Mutex::new(Vec::new()));
+    let (session, _out_rx) = session();
+    let session = session
+        .with_middleware(Record {
+            label: "outer",
+            log: log.clone(),
+        })
+        .with_middleware(TimingMiddleware::new({
+            let timed = timed.clone();
+            move |method, _elapsed| timed.lock().unwrap().push(method.to_string())
+        }))
+        .with_middleware(Auth)
+        .with_middleware(Record {
+            label: "inner",
+            log: log.clone(),
+        });
+
+    let res = session.process_incoming(&request(1, "constant", ())).await;
+    assert_eq!(res.unwrap(), error(1, 403, "Forbidden"));
+    let res = session
+        .process_incoming(&request(2, "upper", ["foo"]))
+        .await;
+    assert_eq!(res.unwrap(), response(2, "FOO"));
+    let res = session.process_incoming(&notification("ping", ())).await;
+    assert!(res.is_none());
+
+    assert_eq!(
+        *log.lock().unwrap(),
+        [
+            "outer constant Some(Number(1))",
+            "outer done Err(403)",
+            "outer upper Some(Number(2))",
+            "inner upper Some(Number(2))",
+            "inner done Ok(RawValue(\"FOO\"))",
+            "outer done Ok(RawValue(\"FOO\"))",
+            "outer ping None",
+            "inner ping None",
+            "inner done Ok(RawValue(null))",
+            "outer done Ok(RawValue(null))",
+        ]
+    );
+    assert_eq!(*timed.lock().unwrap(), ["constant", "upper", "ping"]);
+    Ok(())
+}
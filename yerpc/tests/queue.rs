@@ -0,0 +1,146 @@
+mod common;
+
+use common::{notification, request, response, Api};
+use yerpc::{Error, QueuePolicy, RpcClient, RpcSession};
+
+fn client_with(policy: QueuePolicy) -> (RpcClient, yerpc::OutReceiver) {
+    RpcClient::builder()
+        .queue_capacity(2)
+        .queue_policy(policy)
+        .build()
+}
+
+async fn notify(client: &RpcClient, i: u32) -> yerpc::Result<()> {
+    client.send_notification("tick", Some([i])).await
+}
+
+fn params_of(message: String) -> String {
+    match message.parse().unwrap() {
+        yerpc::Message::Request(request) => serde_json::to_string(&request.params).unwrap(),
+        message => panic!("Unexpected message {message:?}"),
+    }
+}
+
+#[tokio::test]
+async fn queue_policy() -> anyhow::Result<()> {
+    let (client, out_rx) = client_with(QueuePolicy::DropOldestNotification);
+    for i in 1..=3 {
+        notify(&client, i).await?;
+    }
+    assert_eq!(params_of(out_rx.try_recv()?), "[2]");
+    assert_eq!(params_of(out_rx.try_recv()?), "[3]");
+
+    let (client, out_rx) = client_with(QueuePolicy::Fail);
+    notify(&client, 1).await?;
+    notify(&client, 2).await?;
+    let err = notify(&client, 3).await.unwrap_err();
+    assert_eq!(err.code, Error::QUEUE_FULL);
+    assert_eq!(out_rx.len(), 2);
+
+    let (client, out_rx) = client_with(QueuePolicy::Disconnect);
+    notify(&client, 1).await?;
+    notify(&client, 2).await?;
+    assert!(notify(&client, 3).await.unwrap_err().is_disconnnected());
+    assert!(out_rx.recv().await.is_err());
+
+    let (client, out_rx) = client_with(QueuePolicy::Block);
+    notify(&client, 1).await?;
+    notify(&client, 2).await?;
+    let blocked = tokio::spawn({
+        let client = client.clone();
+        async move { notify(&client, 3).await }
+    });
+    tokio::task::yield_now().await;
+    assert!(!blocked.is_finished());
+    assert_eq!(params_of(out_rx.recv().await?), "[1]");
+    blocked.await??;
+    assert_eq!(out_rx.len(), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn disconnect_keeps_responses() -> anyhow::Result<()> {
+    let (session, out_rx) = RpcSession::builder(Api::new())
+        .queue_capacity(1)
+        .queue_policy(QueuePolicy::Disconnect)
+        .build();
+    notify(session.client(), 1).await?;
+    session.handle_incoming(&request(1, "constant", ())).await;
+    assert!(notify(session.client(), 2).await.is_err());
+    assert!(out_rx.is_closed());
+
+    // The response is still delivered, the notification is dropped.
+    assert!(matches!(
+        out_rx.recv().await?.parse(),
+        Ok(yerpc::Message::Response(_))
+    ));
+    assert!(out_rx.recv().await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn cloned_receivers() -> anyhow::Result<()> {
+    let (client, out_rx) = client_with(QueuePolicy::Fail);
+    let other_rx = out_rx.clone();
+    notify(&client, 1).await?;
+    notify(&client, 2).await?;
+    assert_eq!(params_of(other_rx.recv().await?), "[1]");
+    assert_eq!(params_of(out_rx.recv().await?), "[2]");
+
+    // The queue stays open until the last receiver is dropped.
+    drop(other_rx);
+    assert!(!out_rx.is_closed());
+    notify(&client, 3).await?;
+
+    // Closing keeps the queued messages.
+    assert!(out_rx.close());
+    assert!(!out_rx.close());
+    assert!(notify(&client, 4).await.unwrap_err().is_disconnnected());
+    assert_eq!(params_of(out_rx.recv().await?), "[3]");
+    assert!(out_rx.recv().await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn responses_first() -> anyhow::Result<()> {
+    let (session, out_rx) = RpcSession::builder(Api::new())
+        .queue_capacity(1)
+        .queue_policy(QueuePolicy::Fail)
+        .build();
+    notify(session.client(), 1).await?;
+
+    // Responses have their own queue, drained first.
+    session.handle_incoming(&request(1, "constant", ())).await;
+    assert_eq!(out_rx.len(), 2);
+    assert_eq!(out_rx.try_recv()?, response(1, "example"));
+
+    // The policy applies to responses too.
+    session.handle_incoming(&request(2, "constant", ())).await;
+    session.handle_incoming(&request(3, "constant", ())).await;
+    assert_eq!(out_rx.len(), 2);
+    assert_eq!(out_rx.try_recv()?, response(2, "example"));
+    assert_eq!(params_of(out_rx.try_recv()?), "[1]");
+    assert!(out_rx.try_recv().is_err());
+
+    // Progress notifications are queued with the responses,
+    // so they arrive before the response of their request.
+    let (session, out_rx) = RpcSession::builder(Api::new())
+        .queue_capacity(3)
+        .queue_policy(QueuePolicy::DropOldestNotification)
+        .build();
+    for i in 1..=3 {
+        notify(session.client(), i).await?;
+    }
+    session.handle_incoming(&request(4, "export", [3])).await;
+    assert_eq!(
+        out_rx.try_recv()?,
+        notification("$/progress", serde_json::json!({"id": 4, "value": 2}))
+    );
+    assert_eq!(
+        out_rx.try_recv()?,
+        notification("$/progress", serde_json::json!({"id": 4, "value": 3}))
+    );
+    assert_eq!(out_rx.try_recv()?, response(4, "done"));
+    assert_eq!(params_of(out_rx.try_recv()?), "[1]");
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use yerpc::{rpc, Error, RpcClient, RpcServer, RpcSession};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, yerpc::TypeDef)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+struct Positional {
+    pings: Arc<Mutex<usize>>,
+}
+
+#[rpc(
+    all_positional,
+    rust_client,
+    ts_outdir = "../target/test-bindings/positional"
+)]
+impl Positional {
+    /// Adds two numbers.
+    pub async fn add(&self, a: i32, b: i32) -> i32 {
+        a + b
+    }
+
+    #[rpc(name = "negate")]
+    pub async fn neg(&self, point: Point) -> yerpc::Result<Point> {
+        Ok(Point {
+            x: -point.x,
+            y: -point.y,
+        })
+    }
+
+    pub async fn fail(&self) -> yerpc::Result<()> {
+        Err(Error::new(1, "failed".to_string()))
+    }
+
+    #[rpc(notification)]
+    pub async fn ping(&self) {
+        *self.pings.lock().unwrap() += 1;
+    }
+}
+
+mod structured {
+    use super::Point;
+    use yerpc::rpc;
+
+    pub struct Structured;
+
+    #[rpc(rust_client, ts_outdir = "../target/test-bindings/structured")]
+    impl Structured {
+        pub async fn norm(&self, point: Point) -> i32 {
+            point.x.abs() + point.y.abs()
+        }
+    }
+}
+
+/// Connects a typed client to `server`.
+fn connect<T: RpcServer>(server: T) -> RpcClient {
+    let (server, server_out_rx) = RpcSession::create(server);
+    let (session, out_rx) = RpcSession::create(());
+    let client = session.client().clone();
+    tokio::spawn(async move {
+        while let Ok(message) = out_rx.recv().await {
+            server.handle_incoming(&message).await;
+        }
+    });
+    tokio::spawn(async move {
+        while let Ok(message) = server_out_rx.recv().await {
+            session.handle_incoming(&message).await;
+        }
+    });
+    client
+}
+
+#[tokio::test]
+async fn rust_client() -> anyhow::Result<()> {
+    let pings = Arc::new(Mutex::new(0));
+    let client = PositionalClient::new(connect(Positional {
+        pings: pings.clone(),
+    }));
+    assert_eq!(client.add(1, 2).await?, 3);
+    assert_eq!(
+        client.neg(Point { x: 1, y: -2 }).await?,
+        Point { x: -1, y: 2 }
+    );
+    assert_eq!(client.fail().await.unwrap_err().code, 1);
+    client.ping().await?;
+    // Requests are processed in order, so the notification has been handled.
+    assert_eq!(client.add(0, 0).await?, 0);
+    assert_eq!(*pings.lock().unwrap(), 1);
+
+    let client = structured::StructuredClient::new(connect(structured::Structured));
+    assert_eq!(client.norm(Point { x: 3, y: -4 }).await?, 7);
+    Ok(())
+}
+
+#[tokio::test]
+async fn rust_client_bad_result() -> anyhow::Result<()> {
+    let (session, out_rx) = RpcSession::create(());
+    let client = structured::StructuredClient::new(session.client().clone());
+    tokio::spawn(async move {
+        let request: serde_json::Value =
+            serde_json::from_str(&out_rx.recv().await.unwrap()).unwrap();
+        let response =
+            serde_json::json!({"jsonrpc": "2.0", "id": request["id"], "result": "seven"});
+        session.handle_incoming(&response.to_string()).await;
+    });
+    let err = client.norm(Point { x: 3, y: -4 }).await.unwrap_err();
+    assert_eq!(err.code, Error::BAD_RESPONSE);
+    assert!(
+        err.message.contains("invalid type: string \"seven\""),
+        "{}",
+        err.message
+    );
+    Ok(())
+}
@@ -0,0 +1,157 @@
+#![cfg(feature = "tracing")]
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id as SpanId, Record};
+use tracing::{Event, Instrument, Subscriber};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+use yerpc::{rpc, Error, RpcSession};
+
+type Fields = BTreeMap<String, String>;
+/// Fields of the events and the spans they were emitted in.
+type Events = Vec<(Fields, Option<SpanId>)>;
+
+struct Visitor<'a>(&'a mut Fields);
+
+impl Visit for Visitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{value:?}"));
+    }
+}
+
+/// Records the fields of all spans and the span of each event.
+#[derive(Clone, Default)]
+struct Recorder {
+    spans: Arc<Mutex<HashMap<SpanId, Fields>>>,
+    events: Arc<Mutex<Events>>,
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Recorder {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &SpanId, _ctx: Context<'_, S>) {
+        let mut fields = Fields::new();
+        attrs.record(&mut Visitor(&mut fields));
+        self.spans.lock().unwrap().insert(id.clone(), fields);
+    }
+
+    fn on_record(&self, id: &SpanId, values: &Record<'_>, _ctx: Context<'_, S>) {
+        let mut spans = self.spans.lock().unwrap();
+        values.record(&mut Visitor(spans.get_mut(id).unwrap()));
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut fields = Fields::new();
+        event.record(&mut Visitor(&mut fields));
+        let span = ctx.event_span(event).map(|span| span.id());
+        self.events.lock().unwrap().push((fields, span));
+    }
+}
+
+struct Api;
+
+#[rpc(all_positional, ts_outdir = "../target/test-bindings/spans")]
+impl Api {
+    pub async fn greet(&self, name: String) -> yerpc::Result<String> {
+        tracing::info!("greeting {name}");
+        match name.is_empty() {
+            true => Err(Error::new(1, "No name".to_string())),
+            false => Ok(format!("Hello {name}")),
+        }
+    }
+}
+
+#[tokio::test]
+async fn tracing_spans() -> anyhow::Result<()> {
+    let recorder = Recorder::default();
+    let _guard =
+        tracing::subscriber::set_default(tracing_subscriber::registry().with(recorder.clone()));
+
+    let (session, _out_rx) = RpcSession::create(Api);
+    let req = r#"{"jsonrpc":"2.0","method":"greet","params":["alice"],"id":1}"#;
+    session.process_incoming(req).await.unwrap();
+    let req = r#"{"jsonrpc":"2.0","method":"greet","params":[""],"id":"second"}"#;
+    session.process_incoming(req).await.unwrap();
+
+    // Outgoing requests are answered by the remote side.
+    let (session, out_rx) = RpcSession::create(());
+    let client = session.client().clone();
+    tokio::spawn(async move {
+        while let Ok(Ok(yerpc::Message::Request(request))) =
+            out_rx.recv().await.map(|message| message.parse())
+        {
+            let response = yerpc::Response::success(request.id.unwrap(), "pong".into());
+            let response = serde_json::to_string(&response).unwrap();
+            session.handle_incoming(&response).await;
+        }
+    });
+    assert_eq!(client.send_request("ping", Some([1, 2])).await?, "pong");
+
+    // Requests started inside another span do not record into it.
+    let outer = tracing::info_span!("outer", rpc.id = "outer", rpc.params_size = 0);
+    let res = client
+        .start_request("ping", Some([3]))
+        .instrument(outer)
+        .await?;
+    assert_eq!(res.await?, "pong");
+
+    let spans = recorder.spans.lock().unwrap();
+    let mut rpc_spans: Vec<_> = spans.values().cloned().collect();
+    rpc_spans.sort();
+    let fields = |pairs: &[(&str, &str)]| -> Fields {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    };
+    assert_eq!(
+        rpc_spans,
+        [
+            fields(&[
+                ("rpc.direction", "incoming"),
+                ("rpc.error_code", "1"),
+                ("rpc.id", "second"),
+                ("rpc.method", "greet"),
+                ("rpc.outcome", "error"),
+                ("rpc.params_size", "4"),
+            ]),
+            fields(&[
+                ("rpc.direction", "incoming"),
+                ("rpc.id", "1"),
+                ("rpc.method", "greet"),
+                ("rpc.outcome", "ok"),
+                ("rpc.params_size", "9"),
+            ]),
+            fields(&[
+                ("rpc.direction", "outgoing"),
+                ("rpc.id", "1"),
+                ("rpc.method", "ping"),
+                ("rpc.outcome", "ok"),
+                ("rpc.params_size", "5"),
+            ]),
+            fields(&[
+                ("rpc.direction", "outgoing"),
+                ("rpc.id", "2"),
+                ("rpc.method", "ping"),
+                ("rpc.params_size", "3"),
+            ]),
+            fields(&[("rpc.id", "outer"), ("rpc.params_size", "0")]),
+        ]
+    );
+
+    // Events emitted by handlers are tied to the span of the call.
+    let events = recorder.events.lock().unwrap();
+    assert_eq!(events.len(), 2);
+    for (event, span) in events.iter() {
+        let span = &spans[span.as_ref().unwrap()];
+        assert_eq!(span["rpc.method"], "greet");
+        assert!(event["message"].starts_with("greeting"));
+    }
+    Ok(())
+}
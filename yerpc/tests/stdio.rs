@@ -0,0 +1,91 @@
+mod common;
+
+#[cfg(all(test, feature = "support-stdio"))]
+mod tests {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use yerpc::stdio::serve_lines;
+    use yerpc::{Error, Message, Middleware, Next, RawResult, RpcCall};
+
+    /// Answers `pretty` with pretty-printed JSON.
+    struct Pretty;
+
+    #[yerpc::async_trait]
+    impl Middleware for Pretty {
+        async fn handle(&self, call: RpcCall, next: Next<'_>) -> yerpc::Result<RawResult> {
+            if call.method == "pretty" {
+                let json = "{\n  \"b\": [\r\n1],\n  \"a\": \"x\\ny\"\n}";
+                return Ok(serde_json::value::RawValue::from_string(json.to_string()).unwrap());
+            }
+            next.run(call).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lines() -> anyhow::Result<()> {
+        let (session, out_rx) = crate::common::session();
+        let session = session.with_middleware(Pretty);
+        // Small buffers split lines over several reads.
+        let (mut input, reader) = tokio::io::duplex(16);
+        let (writer, output) = tokio::io::duplex(16);
+        let server = tokio::spawn(serve_lines(session, out_rx, reader, writer, 100));
+        let mut output = BufReader::new(output).lines();
+
+        input
+            .write_all(
+                b"{\"jsonrpc\":\"2.0\",\"method\":\"upper\",\"params\":[\"foo\"],\"id\":1}\n",
+            )
+            .await?;
+        assert_eq!(
+            output.next_line().await?.unwrap(),
+            r#"{"jsonrpc":"2.0","id":1,"result":"FOO"}"#
+        );
+
+        // Blank lines are skipped and CRLF is accepted.
+        input.write_all(b"\n\r\n").await?;
+        input
+            .write_all(
+                b"{\"jsonrpc\":\"2.0\",\"method\":\"upper\",\"params\":[\"bar\"],\"id\":2}\r\n",
+            )
+            .await?;
+        assert_eq!(
+            output.next_line().await?.unwrap(),
+            r#"{"jsonrpc":"2.0","id":2,"result":"BAR"}"#
+        );
+
+        // Raw JSON with newlines is written on a single line.
+        input
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"method\":\"pretty\",\"id\":4}\n")
+            .await?;
+        assert_eq!(
+            output.next_line().await?.unwrap(),
+            r#"{"id":4,"jsonrpc":"2.0","result":{"a":"x\ny","b":[1]}}"#
+        );
+
+        // Overlong and invalid lines are answered with parse errors.
+        for line in [vec![b' '; 200], vec![0xff]] {
+            input.write_all(&line).await?;
+            input.write_all(b"\n").await?;
+            let out: Message = output.next_line().await?.unwrap().parse().unwrap();
+            match out {
+                Message::Response(response) => {
+                    assert_eq!(response.id, None);
+                    assert_eq!(response.error.unwrap().code, Error::PARSE_ERROR);
+                }
+                message => panic!("Unexpected message {message:?}"),
+            }
+        }
+
+        // The last line does not need a terminator.
+        input
+            .write_all(br#"{"jsonrpc":"2.0","method":"upper","params":["baz"],"id":3}"#)
+            .await?;
+        drop(input);
+        assert_eq!(
+            output.next_line().await?.unwrap(),
+            r#"{"jsonrpc":"2.0","id":3,"result":"BAZ"}"#
+        );
+        server.await??;
+        assert_eq!(output.next_line().await?, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+mod common;
+
+use common::{request, response, session};
+use futures::stream::{self, Stream};
+use futures_util::StreamExt;
+use serde::{Serialize, Serializer};
+use yerpc::{rpc, Error, Middleware, Next, RpcCall, RpcSession};
+
+#[tokio::test]
+async fn subscription() -> anyhow::Result<()> {
+    let (session, mut out_rx) = session();
+    tokio::spawn(out_rx.forward_subscriptions());
+    session.handle_incoming(&request(1, "count", [2])).await;
+    assert_eq!(out_rx.next().await.unwrap(), response(1, 1));
+    assert_eq!(
+        out_rx.next().await.unwrap(),
+        r#"{"jsonrpc":"2.0","method":"$/subscription","params":{"item":1,"subscription":1}}"#
+    );
+    assert_eq!(
+        out_rx.next().await.unwrap(),
+        r#"{"jsonrpc":"2.0","method":"$/subscription","params":{"item":2,"subscription":1}}"#
+    );
+    assert_eq!(
+        out_rx.next().await.unwrap(),
+        r#"{"jsonrpc":"2.0","method":"$/subscriptionEnd","params":{"subscription":1}}"#
+    );
+
+    // Unsubscribing ends a subscription early.
+    let res = session.process_incoming(&request(2, "watch", ())).await;
+    assert_eq!(res.unwrap(), response(2, 2));
+    let res = session
+        .process_incoming(
+            r#"{"jsonrpc":"2.0","method":"$/unsubscribe","params":{"subscription":2},"id":3}"#,
+        )
+        .await;
+    assert_eq!(res.unwrap(), response(3, true));
+    let out = out_rx.next().await.unwrap();
+    assert_eq!(
+        out,
+        r#"{"jsonrpc":"2.0","method":"$/subscriptionEnd","params":{"subscription":2}}"#
+    );
+
+    // Unknown subscriptions cannot be stopped.
+    let res = session
+        .process_incoming(
+            r#"{"jsonrpc":"2.0","method":"$/unsubscribe","params":{"subscription":2},"id":4}"#,
+        )
+        .await;
+    assert_eq!(res.unwrap(), response(4, false));
+
+    // Subscriptions stop when the receiver is dropped.
+    session.handle_incoming(&request(5, "watch", ())).await;
+    drop(out_rx);
+    tokio::task::yield_now().await;
+    let res = session
+        .process_incoming(
+            r#"{"jsonrpc":"2.0","method":"$/unsubscribe","params":{"subscription":3},"id":6}"#,
+        )
+        .await;
+    assert_eq!(res.unwrap(), response(6, false));
+    Ok(())
+}
+
+/// Item failing to serialize.
+#[derive(yerpc::TypeDef)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
+struct Broken;
+
+impl Serialize for Broken {
+    fn serialize<S: Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+        Err(serde::ser::Error::custom("broken item"))
+    }
+}
+
+struct Items;
+
+#[rpc(ts_outdir = "../target/test-bindings/subscriptions")]
+impl Items {
+    #[rpc(subscription)]
+    pub async fn broken(&self) -> impl Stream<Item = Broken> {
+        stream::iter([Broken, Broken])
+    }
+}
+
+#[tokio::test]
+async fn subscription_error() -> anyhow::Result<()> {
+    let (session, mut out_rx) = RpcSession::create(Items);
+    tokio::spawn(out_rx.forward_subscriptions());
+    session.handle_incoming(&request(1, "broken", ())).await;
+    assert_eq!(out_rx.next().await.unwrap(), response(1, 1));
+    assert_eq!(
+        out_rx.next().await.unwrap(),
+        r#"{"jsonrpc":"2.0","method":"$/subscriptionEnd","params":{"error":{"code":-32603,"message":"broken item"},"subscription":1}}"#
+    );
+    Ok(())
+}
+
+/// Fails calls after the handler succeeded.
+struct FailAfter;
+
+#[yerpc::async_trait]
+impl Middleware for FailAfter {
+    async fn handle(&self, call: RpcCall, next: Next<'_>) -> yerpc::Result<yerpc::RawResult> {
+        next.run(call).await?;
+        Err(Error::new(1, "failed".to_string()))
+    }
+}
+
+#[tokio::test]
+async fn subscription_of_failed_request() -> anyhow::Result<()> {
+    let (session, out_rx) = session();
+    let session = session.with_middleware(FailAfter);
+    tokio::spawn(out_rx.forward_subscriptions());
+
+    // The subscription is only registered once the success response is queued.
+    session.handle_incoming(&request(1, "count", [2])).await;
+    assert_eq!(out_rx.recv().await?, common::error(1, 1, "failed"));
+    tokio::task::yield_now().await;
+    assert!(out_rx.try_recv().is_err());
+    let res = session
+        .process_incoming(
+            r#"{"jsonrpc":"2.0","method":"$/unsubscribe","params":{"subscription":1},"id":2}"#,
+        )
+        .await;
+    assert_eq!(res.unwrap(), response(2, false));
+    Ok(())
+}
+
+#[tokio::test]
+async fn progress() -> anyhow::Result<()> {
+    let (session, out_rx) = session();
+    session.handle_incoming(&request(7, "export", [2])).await;
+    let next = || out_rx.try_recv().unwrap();
+    assert_eq!(
+        next(),
+        r#"{"jsonrpc":"2.0","method":"$/progress","params":{"id":7,"value":1}}"#
+    );
+    assert_eq!(
+        next(),
+        r#"{"jsonrpc":"2.0","method":"$/progress","params":{"id":7,"value":2}}"#
+    );
+    assert_eq!(next(), response(7, "done"));
+
+    // Progress is not part of the params.
+    let res = session
+        .process_incoming(r#"{"jsonrpc":"2.0","method":"export","params":[0, 1],"id":8}"#)
+        .await;
+    let out = res.unwrap();
+    assert_eq!(
+        out,
+        r#"{"jsonrpc":"2.0","id":8,"error":{"code":-32602,"message":"This method takes an array of 1 arguments"}}"#
+    );
+    Ok(())
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use common::{notification, request, response, Api};
+use futures::channel::mpsc;
+use futures::stream;
+use futures_util::StreamExt;
+use std::convert::Infallible;
+use yerpc::transport::Received;
+use yerpc::{Error, RpcSession, TransportError};
+
+#[tokio::test]
+async fn serve_transport() -> anyhow::Result<()> {
+    let api = Api::new();
+    let gate = api.gate();
+    let (session, out_rx) = RpcSession::create(api);
+    let session = session.with_max_concurrent_requests(2);
+    let (in_tx, in_rx) = mpsc::unbounded::<Result<String, Infallible>>();
+    let (out_tx, mut out) = mpsc::unbounded::<String>();
+    let server = tokio::spawn(yerpc::serve(session, out_rx, in_rx, out_tx));
+
+    // Requests are dispatched concurrently.
+    in_tx.unbounded_send(Ok(request(1, "wait", ())))?;
+    in_tx.unbounded_send(Ok(request(2, "upper", ["foo"])))?;
+    assert_eq!(out.next().await.unwrap(), response(2, "FOO"));
+    gate.send(()).await?;
+    assert_eq!(out.next().await.unwrap(), response(1, "done"));
+
+    // Requests over the limit are rejected as busy.
+    in_tx.unbounded_send(Ok(request(3, "wait", ())))?;
+    in_tx.unbounded_send(Ok(request(4, "wait", ())))?;
+    in_tx.unbounded_send(Ok(request(5, "upper", ["bar"])))?;
+    assert_eq!(
+        out.next().await.unwrap(),
+        common::error(5, Error::SERVER_BUSY, "Server busy")
+    );
+    gate.send(()).await?;
+    assert!(out.next().await.unwrap().ends_with(r#""result":"done"}"#));
+    gate.send(()).await?;
+    assert!(out.next().await.unwrap().ends_with(r#""result":"done"}"#));
+
+    // Requests in flight are answered after the incoming stream ends,
+    // then open subscriptions are stopped.
+    in_tx.unbounded_send(Ok(request(6, "watch", ())))?;
+    assert_eq!(out.next().await.unwrap(), response(6, 1));
+    in_tx.unbounded_send(Ok(request(7, "wait", ())))?;
+    drop(in_tx);
+    tokio::task::yield_now().await;
+    gate.send(()).await?;
+    assert_eq!(out.next().await.unwrap(), response(7, "done"));
+    assert_eq!(
+        out.next().await.unwrap(),
+        notification("$/subscriptionEnd", serde_json::json!({"subscription": 1}))
+    );
+    assert_eq!(out.next().await, None);
+    assert!(server.await?.is_ok());
+
+    // Unreadable messages are answered with their error.
+    let (session, out_rx) = common::session();
+    let (out_tx, out) = mpsc::unbounded::<String>();
+    let error = Error::new(Error::PARSE_ERROR, "Bad frame".to_string());
+    let incoming = stream::iter([Ok::<_, Infallible>(Received::Invalid(error))]);
+    yerpc::serve(session, out_rx, incoming, out_tx).await?;
+    assert_eq!(
+        out.collect::<Vec<_>>().await,
+        [common::error(None::<()>, Error::PARSE_ERROR, "Bad frame")]
+    );
+
+    // Errors of the incoming stream stop the transport.
+    let (session, out_rx) = common::session();
+    let (out_tx, _out) = mpsc::unbounded::<String>();
+    let res = yerpc::serve(
+        session,
+        out_rx,
+        stream::iter([Err::<String, _>("broken")]),
+        out_tx,
+    )
+    .await;
+    assert!(matches!(res, Err(TransportError::Recv("broken"))));
+    Ok(())
+}
+
+#[tokio::test]
+async fn serve_callback_at_limit() -> anyhow::Result<()> {
+    let (session, out_rx) = common::session();
+    let session = session.with_max_concurrent_requests(1);
+    let (in_tx, in_rx) = mpsc::unbounded::<Result<String, Infallible>>();
+    let (out_tx, mut out) = mpsc::unbounded::<String>();
+    let server = tokio::spawn(yerpc::serve(session, out_rx, in_rx, out_tx));
+
+    // The response to the request of the handler is read at the limit.
+    in_tx.unbounded_send(Ok(request(1, "ask", ["question"])))?;
+    assert_eq!(
+        out.next().await.unwrap(),
+        request(1, "answer", ["question"])
+    );
+    in_tx.unbounded_send(Ok(response(1, 42)))?;
+    assert_eq!(out.next().await.unwrap(), response(1, 42));
+
+    drop(in_tx);
+    assert_eq!(out.next().await, None);
+    assert!(server.await?.is_ok());
+    Ok(())
+}
@@ -4,12 +4,12 @@ export type JSONValue = (null | boolean | number | string | (JSONValue)[] | {
     [key:string]:JSONValue;
 });
 export type Params = ((JSONValue)[] | Record<string, JSONValue>);
-export type U32 = number;
+export type I64 = number;
 
 /**
  * Request identifier as found in Request and Response objects.
  */
-export type Id = (U32 | string);
+export type Id = (I64 | string | null);
 
 /**
  * Request object.
@@ -33,6 +33,8 @@ export type Request = {
 
     /**
      * Request identifier.
+     * 
+     * Missing for notifications.
      */
     "id"?: Id;
 };
@@ -84,4 +86,10 @@ export type Response = {
      */
     "error"?: Error;
 };
-export type Message = (Request | Response);
+
+/**
+ * JSON-RPC message.
+ * 
+ * A batch is an array of messages sent at once.
+ */
+export type Message = (Request | Response | (Message)[]);